@@ -0,0 +1,19 @@
+-- Classification results, written the first time a track is classified
+
+CREATE TABLE IF NOT EXISTS song_classifications (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    upload_uuid VARCHAR(36) NOT NULL UNIQUE,
+    class_labels TEXT[] NOT NULL,
+    cum_classification REAL[] NOT NULL,
+    major_class VARCHAR(250) NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS feature_classifications (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    song_classification_id BIGINT NOT NULL REFERENCES song_classifications (id) ON DELETE CASCADE,
+    feature VARCHAR(50) NOT NULL,
+    feature_weight REAL NOT NULL,
+    avg_classification REAL[] NOT NULL,
+    per_frame_classifications JSONB NOT NULL
+);
\ No newline at end of file
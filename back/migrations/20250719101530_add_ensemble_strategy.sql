@@ -0,0 +1,4 @@
+-- Ensemble strategy that produced the stored song-level distribution
+
+ALTER TABLE song_classifications
+    ADD COLUMN IF NOT EXISTS strategy VARCHAR(50) NOT NULL DEFAULT 'weighted_mean';
\ No newline at end of file
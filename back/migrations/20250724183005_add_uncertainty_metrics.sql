@@ -0,0 +1,7 @@
+-- Uncertainty of the stored song-level distribution
+
+ALTER TABLE song_classifications
+    ADD COLUMN IF NOT EXISTS entropy REAL NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS margin REAL NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS agreeing_features INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS uncertain BOOLEAN NOT NULL DEFAULT false;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Features left out of a stored result, as [{"feature": ..., "reason": ...}]
+
+ALTER TABLE song_classifications
+    ADD COLUMN IF NOT EXISTS missing_features JSONB NOT NULL DEFAULT '[]';
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Models, inputs and weights a stored result was computed from
+
+ALTER TABLE song_classifications
+    ADD COLUMN IF NOT EXISTS provenance JSONB;
\ No newline at end of file
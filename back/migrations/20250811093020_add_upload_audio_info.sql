@@ -0,0 +1,6 @@
+-- What the upload was before it was decoded to the canonical mono 22050 Hz WAV, NULL for older uploads
+
+ALTER TABLE uploads
+    ADD COLUMN IF NOT EXISTS duration_seconds REAL,
+    ADD COLUMN IF NOT EXISTS sample_rate INTEGER,
+    ADD COLUMN IF NOT EXISTS channels INTEGER;
\ No newline at end of file
@@ -0,0 +1,14 @@
+-- One vector per classified upload for similar-track search, and uploads their owner shared with everyone
+
+CREATE TABLE IF NOT EXISTS track_vectors (
+    upload_uuid VARCHAR(36) PRIMARY KEY,
+    space VARCHAR(100) NOT NULL,
+    vector REAL[] NOT NULL,
+    norm REAL NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS track_vectors_space_idx ON track_vectors (space);
+
+ALTER TABLE uploads
+    ADD COLUMN IF NOT EXISTS shared BOOLEAN NOT NULL DEFAULT false;
\ No newline at end of file
@@ -0,0 +1,24 @@
+-- Sliding-window analysis of whole uploads, one row per analysed 30 s segment
+
+CREATE TABLE IF NOT EXISTS segment_analyses (
+    upload_uuid VARCHAR(36) PRIMARY KEY,
+    class_labels TEXT[] NOT NULL,
+    strategy VARCHAR(50) NOT NULL,
+    segment_count INTEGER NOT NULL,
+    started_at TIMESTAMP NOT NULL,
+    finished_at TIMESTAMP,
+    error TEXT
+);
+
+CREATE TABLE IF NOT EXISTS segment_classifications (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    upload_uuid VARCHAR(36) NOT NULL REFERENCES segment_analyses (upload_uuid) ON DELETE CASCADE,
+    segment_index INTEGER NOT NULL,
+    start_seconds REAL NOT NULL,
+    end_seconds REAL NOT NULL,
+    cum_classification REAL[] NOT NULL,
+    major_class VARCHAR(250),
+    features JSONB NOT NULL,
+    missing_features JSONB NOT NULL,
+    UNIQUE (upload_uuid, segment_index)
+);
\ No newline at end of file
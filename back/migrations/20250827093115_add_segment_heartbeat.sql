@@ -0,0 +1,9 @@
+-- Bumped with every stored segment, an analysis is only taken as abandoned once it stops progressing
+
+ALTER TABLE segment_analyses
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP;
+
+UPDATE segment_analyses SET updated_at = started_at WHERE updated_at IS NULL;
+
+ALTER TABLE segment_analyses
+    ALTER COLUMN updated_at SET NOT NULL;
\ No newline at end of file
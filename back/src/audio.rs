@@ -0,0 +1,382 @@
+//! Decoding of uploads into the canonical form every later step reads: mono, `SAMPLE_RATE`,
+//! stored as a float WAV under `SERVER_DATA/canonical/`.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::audio::{decode::decode_mono, resample::resample};
+use crate::features::SAMPLE_SECONDS;
+use crate::ml::ml::SAMPLE_RATE;
+
+#[derive(Debug)]
+pub enum AudioError {
+    /// Container or codec the decoder doesn't support.
+    Unsupported(String),
+    /// Nothing could be decoded.
+    Undecodable(String),
+    Corrupt { failed_packets: usize, packets: usize },
+    /// Duration of the decoded audio in seconds.
+    TooShort(f32),
+    Resample(String),
+    Encode(String),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::Unsupported(e) => write!(f, "The file's format isn't supported: {}", e),
+            AudioError::Undecodable(e) => write!(f, "The file couldn't be decoded as audio: {}", e),
+            AudioError::Corrupt { failed_packets, packets } => write!(
+                f,
+                "The file is corrupt: {} of {} audio packets couldn't be decoded",
+                failed_packets, packets
+            ),
+            AudioError::TooShort(seconds) => write!(
+                f,
+                "The track is {:.1} s long, it has to be longer than {} s",
+                seconds, SAMPLE_SECONDS
+            ),
+            AudioError::Resample(e) => write!(f, "The audio couldn't be resampled: {}", e),
+            AudioError::Encode(e) => write!(f, "The canonical WAV couldn't be written: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+/// What the upload was before canonicalization, recorded on its `uploads` row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioInfo {
+    pub duration_seconds: f32,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Mono `SAMPLE_RATE` samples of an upload.
+#[derive(Debug, Clone)]
+pub struct CanonicalAudio {
+    pub info: AudioInfo,
+    pub samples: Vec<f32>,
+}
+
+impl CanonicalAudio {
+    /// 32-bit float mono WAV, which librosa loads without resampling.
+    pub fn to_wav(&self) -> Result<Vec<u8>, AudioError> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+
+        let mut wav = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut wav, spec).map_err(|e| AudioError::Encode(e.to_string()))?;
+        for sample in &self.samples {
+            writer.write_sample(*sample).map_err(|e| AudioError::Encode(e.to_string()))?;
+        }
+        writer.finalize().map_err(|e| AudioError::Encode(e.to_string()))?;
+
+        Ok(wav.into_inner())
+    }
+}
+
+/// Decodes an upload, averages its channels like `librosa.to_mono` and resamples it to
+/// `SAMPLE_RATE`. Fails unless the track is long enough for the middle `SAMPLE_SECONDS` sample.
+pub fn canonicalize(bytes: Vec<u8>, extension: &str) -> Result<CanonicalAudio, AudioError> {
+    let (info, mono) = decode_mono(bytes, extension)?;
+
+    if info.duration_seconds <= SAMPLE_SECONDS as f32 {
+        return Err(AudioError::TooShort(info.duration_seconds));
+    }
+
+    let samples = resample(&mono, info.sample_rate, SAMPLE_RATE)?;
+
+    Ok(CanonicalAudio { info, samples })
+}
+
+pub fn canonical_path(server_data: &Path, upload_uuid: &str) -> PathBuf {
+    server_data.join("canonical").join(format!("{}.wav", upload_uuid))
+}
+
+/// Samples of a canonical WAV written by `CanonicalAudio::to_wav`.
+pub fn read_canonical(path: &Path) -> Result<Vec<f32>, AudioError> {
+    let file = File::open(path).map_err(|e| AudioError::Undecodable(format!("{}: {}", path.display(), e)))?;
+
+    read_wav(BufReader::new(file))
+}
+
+fn read_wav<R: Read>(reader: R) -> Result<Vec<f32>, AudioError> {
+    let reader = hound::WavReader::new(reader).map_err(|e| AudioError::Undecodable(e.to_string()))?;
+    let spec = reader.spec();
+
+    if spec.channels != 1 || spec.sample_rate != SAMPLE_RATE || spec.sample_format != hound::SampleFormat::Float {
+        return Err(AudioError::Unsupported(format!(
+            "expected mono {} Hz float samples, got {:?}",
+            SAMPLE_RATE, spec
+        )));
+    }
+
+    reader
+        .into_samples::<f32>()
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|e| AudioError::Undecodable(e.to_string()))
+}
+
+pub mod decode {
+
+    use std::io::Cursor;
+
+    use symphonia::core::{
+        audio::SampleBuffer,
+        codecs::{DecoderOptions, CODEC_TYPE_NULL},
+        errors::Error,
+        formats::FormatOptions,
+        io::MediaSourceStream,
+        meta::MetadataOptions,
+        probe::Hint,
+    };
+
+    use crate::audio::{AudioError, AudioInfo};
+
+    /// Share of packets that may fail to decode before the file counts as corrupt. A few bad
+    /// frames are common in MP3s and only cost a few milliseconds of audio.
+    const MAX_FAILED_PACKETS: f32 = 0.01;
+
+    /// Decodes the first audio track and averages its channels.
+    pub fn decode_mono(bytes: Vec<u8>, extension: &str) -> Result<(AudioInfo, Vec<f32>), AudioError> {
+        let stream = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+
+        let mut hint = Hint::new();
+        hint.with_extension(extension);
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+            .map_err(into_audio_error)?;
+        let mut format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(AudioError::Undecodable("no audio track".to_string()))?;
+        let track_id = track.id;
+
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(into_audio_error)?;
+
+        let mut sample_rate = track.codec_params.sample_rate;
+        let mut channels = track.codec_params.channels.map(|channels| channels.count());
+        let mut mono = Vec::new();
+        let mut buffer: Option<SampleBuffer<f32>> = None;
+        let (mut packets, mut failed_packets) = (0, 0);
+
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(into_audio_error(e)),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+            packets += 1;
+
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(Error::DecodeError(e)) => {
+                    tracing::debug!("Skipping undecodable packet: {}", e);
+                    failed_packets += 1;
+                    continue;
+                }
+                Err(e) => return Err(into_audio_error(e)),
+            };
+
+            let spec = *decoded.spec();
+            let count = spec.channels.count();
+            sample_rate.get_or_insert(spec.rate);
+            channels.get_or_insert(count);
+
+            let buffer = match &mut buffer {
+                Some(buffer) if buffer.capacity() >= decoded.capacity() * count => buffer,
+                _ => buffer.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+            };
+            buffer.copy_interleaved_ref(decoded);
+
+            mono.extend(
+                buffer
+                    .samples()
+                    .chunks_exact(count)
+                    .map(|frame| frame.iter().sum::<f32>() / count as f32),
+            );
+        }
+
+        if mono.is_empty() {
+            return Err(AudioError::Undecodable("no audio samples".to_string()));
+        }
+        if failed_packets as f32 > MAX_FAILED_PACKETS * packets as f32 {
+            return Err(AudioError::Corrupt { failed_packets, packets });
+        }
+        if failed_packets > 0 {
+            tracing::warn!("{} of {} packets couldn't be decoded and were skipped", failed_packets, packets);
+        }
+
+        let sample_rate = sample_rate.ok_or(AudioError::Undecodable("unknown sample rate".to_string()))?;
+        let info = AudioInfo {
+            duration_seconds: mono.len() as f32 / sample_rate as f32,
+            sample_rate,
+            channels: channels.unwrap_or(1) as u16,
+        };
+
+        Ok((info, mono))
+    }
+
+    fn into_audio_error(e: Error) -> AudioError {
+        match e {
+            Error::Unsupported(e) => AudioError::Unsupported(e.to_string()),
+            e => AudioError::Undecodable(e.to_string()),
+        }
+    }
+}
+
+pub mod resample {
+
+    use rubato::{FftFixedIn, Resampler};
+
+    use crate::audio::AudioError;
+
+    /// Input frames resampled per FFT chunk.
+    const CHUNK: usize = 8192;
+
+    /// Band-limited FFT resampling from `from` to `to` Hz, aligned so the output starts at the
+    /// same instant as the input and holds `ceil(len * to / from)` samples.
+    pub fn resample(samples: &[f32], from: u32, to: u32) -> Result<Vec<f32>, AudioError> {
+        if from == to {
+            return Ok(samples.to_vec());
+        }
+
+        let resample_error = |e: String| AudioError::Resample(format!("{} Hz to {} Hz: {}", from, to, e));
+
+        let mut resampler = FftFixedIn::<f32>::new(from as usize, to as usize, CHUNK, 2, 1)
+            .map_err(|e| resample_error(e.to_string()))?;
+
+        let expected = (samples.len() as u64 * to as u64).div_ceil(from as u64) as usize;
+        let delay = resampler.output_delay();
+        let mut resampled = Vec::with_capacity(expected + delay + CHUNK);
+
+        let mut chunks = samples.chunks_exact(resampler.input_frames_next());
+        for chunk in &mut chunks {
+            let output = resampler.process(&[chunk], None).map_err(|e| resample_error(e.to_string()))?;
+            resampled.extend_from_slice(&output[0]);
+        }
+
+        let mut rest = Some(chunks.remainder());
+        while resampled.len() < expected + delay {
+            let output = resampler
+                .process_partial(rest.take().map(|rest| [rest]).as_ref().map(|rest| &rest[..]), None)
+                .map_err(|e| resample_error(e.to_string()))?;
+            resampled.extend_from_slice(&output[0]);
+        }
+
+        resampled.drain(..delay);
+        resampled.truncate(expected);
+
+        Ok(resampled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    fn wav(seconds: f32, sample_rate: u32, channels: u16) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut wav = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+        for i in 0..(seconds * sample_rate as f32) as usize {
+            let value = (2.0 * PI * 440.0 * i as f32 / sample_rate as f32).sin();
+            for channel in 0..channels {
+                // the second channel is silent, so the mono mix has half the amplitude
+                let value = if channel == 0 { value } else { 0.0 };
+                writer.write_sample((value * i16::MAX as f32) as i16).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+
+        wav.into_inner()
+    }
+
+    #[test]
+    fn stereo_upload_is_downmixed_and_resampled() {
+        let canonical = canonicalize(wav(31.0, 44100, 2), "wav").unwrap();
+
+        assert_eq!(
+            canonical.info,
+            AudioInfo {
+                duration_seconds: 31.0,
+                sample_rate: 44100,
+                channels: 2
+            }
+        );
+        assert_eq!(canonical.samples.len(), 31 * SAMPLE_RATE as usize);
+
+        let middle = &canonical.samples[SAMPLE_RATE as usize..2 * SAMPLE_RATE as usize];
+        let peak = middle.iter().fold(0.0f32, |a, b| a.max(b.abs()));
+        assert!((peak - 0.5).abs() < 0.01, "peak {}", peak);
+
+        // 440 Hz keeps its period of 50.1 samples at 22050 Hz
+        let crossings = middle.windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0).count();
+        assert!((439..=441).contains(&crossings), "{} crossings", crossings);
+    }
+
+    #[test]
+    fn canonical_wav_is_mono_at_the_model_sample_rate() {
+        let canonical = canonicalize(wav(31.0, 22050, 1), "wav").unwrap();
+
+        let reader = hound::WavReader::new(Cursor::new(canonical.to_wav().unwrap())).unwrap();
+
+        assert_eq!(reader.spec().channels, 1);
+        assert_eq!(reader.spec().sample_rate, SAMPLE_RATE);
+        assert_eq!(reader.len() as usize, canonical.samples.len());
+        assert_eq!(read_wav(Cursor::new(canonical.to_wav().unwrap())).unwrap(), canonical.samples);
+    }
+
+    #[test]
+    fn short_tracks_are_rejected() {
+        assert!(matches!(
+            canonicalize(wav(5.0, 22050, 1), "wav"),
+            Err(AudioError::TooShort(seconds)) if seconds == 5.0
+        ));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        let garbage: Vec<u8> = (0..64 * 1024).map(|i| (i * 7919 % 251) as u8).collect();
+
+        assert!(matches!(
+            canonicalize(garbage, "mp3"),
+            Err(AudioError::Undecodable(_) | AudioError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_header_is_rejected() {
+        let mut wav = wav(31.0, 22050, 1);
+        wav.truncate(20);
+
+        assert!(canonicalize(wav, "wav").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Zip of everything stored for one upload, so an analysis can be archived without copying
+//! files out of `SERVER_DATA` by hand.
+
+use std::{
+    env, fmt,
+    fs::File,
+    io::{self, Seek, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::ml::ml::{find_signal_path_in, sample_file_name, Feature, FeatureDetail};
+
+pub const CLASSIFICATION_FILE: &str = "classification.json";
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Bundles written at once when `BUNDLE_BUILDS` is unset.
+pub const DEFAULT_BUNDLE_BUILDS: usize = 2;
+
+/// Each bundle reads every file of an upload and fills a temporary file as large, so only
+/// `BUNDLE_BUILDS` are written at once.
+pub fn bundle_builds_from_env() -> Arc<Semaphore> {
+    let builds = env::var("BUNDLE_BUILDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_BUNDLE_BUILDS);
+
+    Arc::new(Semaphore::new(builds))
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+    Io(String),
+    Zip(String),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::Io(e) => write!(f, "A file of the bundle couldn't be read: {}", e),
+            BundleError::Zip(e) => write!(f, "The bundle couldn't be written: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+impl From<zip::result::ZipError> for BundleError {
+    fn from(e: zip::result::ZipError) -> Self {
+        BundleError::Zip(e.to_string())
+    }
+}
+
+/// A file under `SERVER_DATA` and where it's put in the archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleFile {
+    pub source: PathBuf,
+    pub archive_path: String,
+}
+
+/// Written into the archive as `MANIFEST_FILE`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleManifest {
+    pub upload_name: String,
+    pub created_at: DateTime<Utc>,
+    /// Archive paths of the files that were found.
+    pub files: Vec<String>,
+    /// Archive paths of the files the ETL hasn't written (yet).
+    pub missing: Vec<String>,
+}
+
+/// Whether `upload_name` stays a single file name when joined to a directory.
+pub fn is_plain_file_name(upload_name: &str) -> bool {
+    let mut components = Path::new(upload_name).components();
+
+    matches!((components.next(), components.next()), (Some(Component::Normal(_)), None))
+}
+
+/// Every file an upload may have: the original audio, the 30 s sample, the feature arrays
+/// and the ETL videos. Whether they exist is only checked when the bundle is written.
+pub fn bundle_files(server_data: &Path, upload_name: &str) -> Vec<BundleFile> {
+    let sample = sample_file_name(upload_name);
+    let features_path = server_data.join("features").join(upload_name);
+
+    let mut files = vec![
+        BundleFile {
+            source: server_data.join("uploads").join(upload_name),
+            archive_path: format!("audio/{}", upload_name),
+        },
+        BundleFile {
+            source: server_data.join("30s").join(&sample),
+            archive_path: format!("sample/{}", sample),
+        },
+    ];
+
+    for feature in Feature::all() {
+        let source = find_signal_path_in(&features_path, &feature);
+        let relative = source.strip_prefix(&features_path).unwrap_or(&source).to_string_lossy().to_string();
+
+        files.push(BundleFile {
+            source,
+            archive_path: format!("features/{}", relative),
+        });
+    }
+
+    for feature in Feature::all() {
+        let folder = FeatureDetail::get_folder(&feature);
+
+        files.push(BundleFile {
+            source: server_data
+                .join(&folder)
+                .join(upload_name)
+                .join("video")
+                .join(format!("{}.mp4", upload_name)),
+            archive_path: format!("videos/{}/{}.mp4", folder, upload_name),
+        });
+    }
+
+    files
+}
+
+/// Writes the files that exist, the classification JSON and the manifest into a zip.
+/// Audio and video are already compressed and are stored as they are.
+pub fn write_bundle<W: Write + Seek>(
+    writer: W,
+    upload_name: &str,
+    files: &[BundleFile],
+    classification_json: &[u8],
+) -> Result<(W, BundleManifest), BundleError> {
+    let mut zip = ZipWriter::new(writer);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let mut manifest = BundleManifest {
+        upload_name: upload_name.to_string(),
+        created_at: Utc::now(),
+        files: Vec::new(),
+        missing: Vec::new(),
+    };
+
+    for file in files {
+        let mut source = match File::open(&file.source) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                manifest.missing.push(file.archive_path.clone());
+                continue;
+            }
+            Err(e) => return Err(BundleError::Io(format!("{}: {}", file.source.display(), e))),
+        };
+
+        let options = match file.source.extension().and_then(|e| e.to_str()) {
+            Some("mp3") | Some("mp4") => stored,
+            _ => deflated,
+        };
+
+        zip.start_file(file.archive_path.as_str(), options)?;
+        io::copy(&mut source, &mut zip).map_err(|e| BundleError::Io(format!("{}: {}", file.source.display(), e)))?;
+        manifest.files.push(file.archive_path.clone());
+    }
+
+    zip.start_file(CLASSIFICATION_FILE, deflated)?;
+    zip.write_all(classification_json).map_err(|e| BundleError::Zip(e.to_string()))?;
+
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| BundleError::Zip(e.to_string()))?;
+    zip.start_file(MANIFEST_FILE, deflated)?;
+    zip.write_all(&manifest_json).map_err(|e| BundleError::Zip(e.to_string()))?;
+
+    Ok((zip.finish()?, manifest))
+}
+
+/// Download name of the bundle, upload names may contain characters a header can't carry.
+pub fn bundle_file_name(upload_name: &str) -> String {
+    let stem = upload_name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(upload_name);
+    let stem: String = stem
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+
+    format!("{}.zip", stem)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{Cursor, Read};
+
+    use super::*;
+
+    #[test]
+    fn bundle_lists_every_file_and_records_missing_ones() {
+        let server_data = std::env::temp_dir().join(format!("bundle-test-{}", std::process::id()));
+        let upload_name = "8d298e5b-e11a-4ab4-ab38-7149c710a90a-track.mp3";
+
+        let files = bundle_files(&server_data, upload_name);
+        // audio, sample, nine feature arrays and nine videos
+        assert_eq!(files.len(), 20);
+        assert!(files.iter().any(|f| f.archive_path == "sample/8d298e5b-e11a-4ab4-ab38-7149c710a90a-track.wav"));
+        assert!(files.iter().any(|f| f.archive_path == "features/mfcc/mfcc.npy"));
+
+        std::fs::create_dir_all(server_data.join("uploads")).unwrap();
+        std::fs::write(server_data.join("uploads").join(upload_name), b"mp3 bytes").unwrap();
+
+        let (zip, manifest) = write_bundle(Cursor::new(Vec::new()), upload_name, &files, b"{}").unwrap();
+        std::fs::remove_dir_all(&server_data).unwrap();
+
+        assert_eq!(manifest.files, vec![format!("audio/{}", upload_name)]);
+        assert_eq!(manifest.missing.len(), 19);
+
+        let mut archive = zip::ZipArchive::new(zip).unwrap();
+        assert_eq!(archive.len(), 3);
+
+        let mut audio = String::new();
+        archive.by_name(&format!("audio/{}", upload_name)).unwrap().read_to_string(&mut audio).unwrap();
+        assert_eq!(audio, "mp3 bytes");
+        assert!(archive.by_name(CLASSIFICATION_FILE).is_ok());
+        assert!(archive.by_name(MANIFEST_FILE).is_ok());
+    }
+
+    #[test]
+    fn only_plain_file_names_are_bundled() {
+        assert!(is_plain_file_name("8d298e5b-e11a-4ab4-ab38-7149c710a90a-track.mp3"));
+        assert!(!is_plain_file_name("8d298e5b-e11a-4ab4-ab38-7149c710a90a-../../etc/passwd"));
+        assert!(!is_plain_file_name("/etc/passwd"));
+        assert!(!is_plain_file_name(".."));
+        assert!(!is_plain_file_name(""));
+    }
+
+    #[test]
+    fn bundle_file_name_is_header_safe() {
+        assert_eq!(
+            bundle_file_name("8d298e5b-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3"),
+            "8d298e5b-faintofficialmusicvideo4kupgradelinkinpark.zip"
+        );
+    }
+}
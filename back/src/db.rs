@@ -9,6 +9,7 @@ pub mod db_conn {
     use std::env;
     use uuid::Uuid;
 
+    use crate::audio::AudioInfo;
     use crate::http::handlers::delete::DeleteStatus;
     #[allow(dead_code)]
     #[derive(Debug)]
@@ -93,6 +94,18 @@ pub mod db_conn {
         pub file_name: String,
         pub added: NaiveDateTime,
         pub ready: bool,
+        pub duration_seconds: Option<f32>,
+        pub sample_rate: Option<i32>,
+        pub channels: Option<i32>,
+        /// Shared uploads show up in every user's library-wide similar-track search.
+        pub shared: bool,
+    }
+
+    impl Upload {
+        /// `<upload_uuid>-<file_name>`, the name used in urls and under `SERVER_DATA`.
+        pub fn upload_name(&self) -> String {
+            format!("{}-{}", self.upload_uuid, self.file_name)
+        }
     }
 
 
@@ -109,6 +122,10 @@ pub mod db_conn {
                 file_name: "song".to_string(),
                 added: Local::now().naive_local(),
                 ready: false,
+                duration_seconds: None,
+                sample_rate: None,
+                channels: None,
+                shared: false,
             },
         );
 
@@ -120,7 +137,7 @@ pub mod db_conn {
         let mut tx = pool.begin().await.expect("should create transaction");
 
         let uploads_vec = sqlx::query_as::<_, Upload>(
-            "SELECT DISTINCT id, user_uuid, upload_uuid, file_name, added, ready from uploads where user_uuid = $1"
+            "SELECT DISTINCT id, user_uuid, upload_uuid, file_name, added, ready, duration_seconds, sample_rate, channels, shared from uploads where user_uuid = $1"
         )
         .bind(user_uuid)
         .fetch_all(&mut *tx)
@@ -150,6 +167,18 @@ pub mod db_conn {
         .execute(&mut *tx)
         .await;
 
+        let _ = sqlx::query("DELETE FROM song_classifications")
+            .execute(&mut *tx)
+            .await;
+
+        let _ = sqlx::query("DELETE FROM track_vectors")
+            .execute(&mut *tx)
+            .await;
+
+        let _ = sqlx::query("DELETE FROM segment_analyses")
+            .execute(&mut *tx)
+            .await;
+
         tx.commit().await.expect("Transaction should be closed");
 
         match uploads_vec {
@@ -194,7 +223,7 @@ pub mod db_conn {
         let mut tx = pool.begin().await.expect("should create transaction");
 
         let upload = sqlx::query_as::<_, Upload>(
-            "SELECT id, user_uuid, upload_uuid, file_name, added from uploads where upload_uuid = $1"
+            "SELECT id, user_uuid, upload_uuid, file_name, added, ready, duration_seconds, sample_rate, channels, shared from uploads where upload_uuid = $1"
         )
         .bind(&upload_uuid)
         .fetch_one(&mut *tx)
@@ -214,21 +243,22 @@ pub mod db_conn {
         }
     }
 
-    pub async fn insert_upload_to_db(user_uuid: &String, file_name: &String) -> Upload {
+    pub async fn insert_upload_to_db(user_uuid: &String, file_name: &String, audio: &AudioInfo) -> Upload {
         let pool = get_pool().await;
         let upload_uuid = Uuid::new_v4().to_string();
 
         
         let mut tx = pool.begin().await.expect("should create transaction");
         
-        info!("Creating query: Insert in (INSERT INTO uploads (user_uuid, upload_uuid, file_name, added, ready) values (?, ?, ?, CURRENT_TIMESTAMP, false)");
+        info!("Creating query: Insert in (INSERT INTO uploads (user_uuid, upload_uuid, file_name, added, ready, duration_seconds, sample_rate, channels) values (?, ?, ?, CURRENT_TIMESTAMP, false, ?, ?, ?)");
         info!("Provided user_uuid: {:?}", &user_uuid);
         info!("Provided upload_uuid{:?}", &upload_uuid);
         info!("Provided file_name: {:?}", &file_name);
+        info!("Provided audio: {:?}", &audio);
         
         let query = query!(
-            r#"INSERT INTO uploads (user_uuid, upload_uuid, file_name, added, ready) values ($1, $2, $3, CURRENT_TIMESTAMP, false)"#,
-         user_uuid, &upload_uuid, file_name)
+            r#"INSERT INTO uploads (user_uuid, upload_uuid, file_name, added, ready, duration_seconds, sample_rate, channels) values ($1, $2, $3, CURRENT_TIMESTAMP, false, $4, $5, $6)"#,
+         user_uuid, &upload_uuid, file_name, audio.duration_seconds, audio.sample_rate as i32, audio.channels as i32)
         .execute(&mut *tx)
         .await
         .expect(&format!("Should insert record for {} {}", user_uuid, file_name));
@@ -236,7 +266,7 @@ pub mod db_conn {
         info!("UPLOAD INSERTED SUCCESSFULLY: {:?}", query);
 
         let user_struct: Upload = sqlx::query_as::<_, Upload>(
-            "SELECT id, user_uuid, upload_uuid, file_name, added, ready from uploads where upload_uuid = $1"
+            "SELECT id, user_uuid, upload_uuid, file_name, added, ready, duration_seconds, sample_rate, channels, shared from uploads where upload_uuid = $1"
         )
         .bind(upload_uuid)
         .fetch_one(&mut *tx)
@@ -267,6 +297,26 @@ pub mod db_conn {
                 .await
                 .expect("Delete be possible at this point");
 
+        if row_delete.rows_affected() > 0 {
+            sqlx::query("DELETE FROM song_classifications where upload_uuid = $1")
+                .bind(&upload_uuid)
+                .execute(&mut *tx)
+                .await
+                .expect("Delete be possible at this point");
+
+            sqlx::query("DELETE FROM track_vectors where upload_uuid = $1")
+                .bind(&upload_uuid)
+                .execute(&mut *tx)
+                .await
+                .expect("Delete be possible at this point");
+
+            sqlx::query("DELETE FROM segment_analyses where upload_uuid = $1")
+                .bind(&upload_uuid)
+                .execute(&mut *tx)
+                .await
+                .expect("Delete be possible at this point");
+        }
+
         match tx.commit().await {
             Ok(a) => {
                 tracing::info!("DELETE REQUEST FULFILLED for {:?}, upload_uuid {:?}", &user_uuid, &upload_uuid);
@@ -308,3 +358,465 @@ pub mod db_conn {
 
 
 }
+
+
+#[allow(unused)]
+pub mod results {
+
+    use std::collections::BTreeMap;
+
+    use sqlx::{prelude::FromRow, types::Json};
+
+    use crate::db::db_conn::{get_pool, SqlError};
+    use crate::db::similarity::upsert_track_vector;
+    use crate::ml::ensemble::Ensemble;
+    use crate::ml::ml::{Feature, FeatureClassificationResult, MissingFeature, SongClassificationResult};
+    use crate::ml::provenance::Provenance;
+    use crate::ml::similarity::TrackVector;
+
+    #[derive(FromRow, Debug)]
+    pub struct StoredSongClassification {
+        pub id: i64,
+        pub class_labels: Vec<String>,
+        pub strategy: String,
+        pub missing_features: Json<Vec<MissingFeature>>,
+        pub provenance: Option<Json<Provenance>>,
+    }
+
+    #[derive(FromRow, Debug)]
+    pub struct StoredFeatureClassification {
+        pub feature: String,
+        pub feature_weight: f32,
+        pub per_frame_classifications: Json<Vec<Vec<f32>>>,
+    }
+
+    /// Upload names used in urls are `<upload_uuid>-<file_name>`.
+    pub fn upload_uuid_from_name(upload_name: &str) -> Option<&str> {
+        upload_name
+            .get(..36)
+            .filter(|uuid| uuid::Uuid::parse_str(uuid).is_ok())
+    }
+
+    /// Stores the result, replacing any previous one for the upload, and marks the upload as ready.
+    pub async fn save_classification(
+        upload_uuid: &str,
+        result: &SongClassificationResult,
+    ) -> Result<(), SqlError> {
+        let pool = get_pool().await;
+        let mut tx = pool.begin().await.expect("should create transaction");
+
+        let to_sql_error = |e: sqlx::Error| {
+            SqlError::UploadQueryError(format!(
+                "Classification couldn't be saved. {} \n {}",
+                upload_uuid, e
+            ))
+        };
+
+        sqlx::query("DELETE FROM song_classifications where upload_uuid = $1")
+            .bind(upload_uuid)
+            .execute(&mut *tx)
+            .await
+            .map_err(to_sql_error)?;
+
+        let song_classification_id: i64 = sqlx::query_scalar(
+            "INSERT INTO song_classifications (upload_uuid, class_labels, cum_classification, major_class, strategy, entropy, margin, agreeing_features, uncertain, missing_features, provenance, created_at) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, CURRENT_TIMESTAMP) RETURNING id"
+        )
+        .bind(upload_uuid)
+        .bind(&result.class_labels)
+        .bind(&result.cum_classification)
+        .bind(&result.major_class.label)
+        .bind(result.strategy.key())
+        .bind(result.uncertainty.entropy)
+        .bind(result.uncertainty.margin)
+        .bind(result.uncertainty.agreeing_features as i32)
+        .bind(result.uncertainty.uncertain)
+        .bind(Json(&result.missing_features))
+        .bind(result.provenance.as_ref().map(Json))
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(to_sql_error)?;
+
+        for feature_result in &result.feature_classification_result {
+            let per_frame: Vec<&Vec<f32>> = feature_result.per_frame_classifications.values().collect();
+
+            sqlx::query(
+                "INSERT INTO feature_classifications (song_classification_id, feature, feature_weight, avg_classification, per_frame_classifications) values ($1, $2, $3, $4, $5)"
+            )
+            .bind(song_classification_id)
+            .bind(feature_result.feature.key())
+            .bind(feature_result.feature_weight)
+            .bind(&feature_result.avg_classification)
+            .bind(Json(per_frame))
+            .execute(&mut *tx)
+            .await
+            .map_err(to_sql_error)?;
+        }
+
+        upsert_track_vector(&mut tx, upload_uuid, &TrackVector::from_result(result))
+            .await
+            .map_err(to_sql_error)?;
+
+        sqlx::query("UPDATE uploads SET ready = true where upload_uuid = $1")
+            .bind(upload_uuid)
+            .execute(&mut *tx)
+            .await
+            .map_err(to_sql_error)?;
+
+        tx.commit().await.map_err(to_sql_error)?;
+
+        tracing::info!("Classification stored for {}", upload_uuid);
+
+        Ok(())
+    }
+
+    /// Rebuilds a previously stored result, `None` if the upload was never classified.
+    pub async fn get_classification(
+        upload_uuid: &str,
+        upload_name: &str,
+    ) -> Result<Option<SongClassificationResult>, SqlError> {
+        let pool = get_pool().await;
+
+        let to_sql_error = |e: sqlx::Error| {
+            SqlError::UploadQueryError(format!(
+                "Classification couldn't be fetched. {} \n {}",
+                upload_uuid, e
+            ))
+        };
+
+        let song = sqlx::query_as::<_, StoredSongClassification>(
+            "SELECT id, class_labels, strategy, missing_features, provenance from song_classifications where upload_uuid = $1"
+        )
+        .bind(upload_uuid)
+        .fetch_optional(&pool)
+        .await
+        .map_err(to_sql_error)?;
+
+        let Some(song) = song else {
+            return Ok(None);
+        };
+
+        let features = sqlx::query_as::<_, StoredFeatureClassification>(
+            "SELECT feature, feature_weight, per_frame_classifications from feature_classifications where song_classification_id = $1 ORDER BY id"
+        )
+        .bind(song.id)
+        .fetch_all(&pool)
+        .await
+        .map_err(to_sql_error)?;
+
+        let mut classifications = Vec::with_capacity(features.len());
+
+        for stored in features {
+            let feature = Feature::from_key(&stored.feature).ok_or(SqlError::UploadQueryError(
+                format!("Unknown feature {} stored for {}", stored.feature, upload_uuid),
+            ))?;
+
+            let per_frame: BTreeMap<i64, Vec<f32>> = stored
+                .per_frame_classifications
+                .0
+                .into_iter()
+                .enumerate()
+                .map(|(idx, row)| (idx as i64, row))
+                .collect();
+
+            classifications.push(FeatureClassificationResult::from_frames(
+                feature,
+                stored.feature_weight,
+                per_frame,
+            ));
+        }
+
+        let strategy: Ensemble = song.strategy.parse().map_err(SqlError::UploadQueryError)?;
+
+        let mut result = SongClassificationResult::from_features(
+            upload_name.to_string(),
+            song.class_labels,
+            classifications,
+            song.missing_features.0,
+            strategy,
+        );
+        result.provenance = song.provenance.map(|provenance| provenance.0);
+
+        Ok(Some(result))
+    }
+}
+
+#[allow(unused)]
+pub mod similarity {
+
+    use serde::Serialize;
+    use sqlx::{prelude::FromRow, PgConnection};
+
+    use crate::db::db_conn::{get_pool, SqlError};
+    use crate::ml::similarity::{SimilarityScope, TrackVector};
+
+    #[derive(FromRow, Debug, Serialize)]
+    pub struct SimilarTrack {
+        pub upload_uuid: String,
+        pub file_name: String,
+        /// Cosine similarity in `[-1, 1]`, 1 for tracks pointing the same way.
+        pub similarity: f32,
+        /// Whether the track belongs to the owner of the queried upload.
+        pub own: bool,
+    }
+
+    /// Replaces the upload's vector, inside the caller's transaction.
+    pub async fn upsert_track_vector(
+        conn: &mut PgConnection,
+        upload_uuid: &str,
+        vector: &TrackVector,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO track_vectors (upload_uuid, space, vector, norm, created_at) values ($1, $2, $3, $4, CURRENT_TIMESTAMP) ON CONFLICT (upload_uuid) DO UPDATE SET space = EXCLUDED.space, vector = EXCLUDED.vector, norm = EXCLUDED.norm, created_at = EXCLUDED.created_at"
+        )
+        .bind(upload_uuid)
+        .bind(&vector.space)
+        .bind(&vector.values)
+        .bind(vector.norm())
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores the vector of a result classified before vectors were recorded.
+    pub async fn save_track_vector(upload_uuid: &str, vector: &TrackVector) -> Result<(), SqlError> {
+        let pool = get_pool().await;
+        let mut conn = pool.acquire().await.map_err(|e| to_sql_error(upload_uuid, e))?;
+
+        upsert_track_vector(&mut conn, upload_uuid, vector)
+            .await
+            .map_err(|e| to_sql_error(upload_uuid, e))
+    }
+
+    /// Space of the upload's stored vector, `None` if it has none yet.
+    pub async fn get_track_vector_space(upload_uuid: &str) -> Result<Option<String>, SqlError> {
+        sqlx::query_scalar("SELECT space from track_vectors where upload_uuid = $1")
+            .bind(upload_uuid)
+            .fetch_optional(&get_pool().await)
+            .await
+            .map_err(|e| to_sql_error(upload_uuid, e))
+    }
+
+    /// The `k` uploads whose vectors are closest to the upload's by cosine similarity.
+    /// Only vectors of the same space and length are compared.
+    /// Tracks of `user_uuid`, and with `SimilarityScope::Library` every shared track, closest to the upload.
+    pub async fn similar_tracks(
+        upload_uuid: &str,
+        user_uuid: &str,
+        k: i64,
+        scope: SimilarityScope,
+    ) -> Result<Vec<SimilarTrack>, SqlError> {
+        sqlx::query_as::<_, SimilarTrack>(
+            r#"SELECT u.upload_uuid, u.file_name, u.user_uuid = $4 AS own,
+                (SELECT SUM(a * b) FROM unnest(v.vector, q.vector) AS t(a, b)) / (v.norm * q.norm) AS similarity
+            FROM track_vectors v
+            JOIN uploads u ON u.upload_uuid = v.upload_uuid
+            JOIN (
+                SELECT space, vector, norm FROM track_vectors WHERE upload_uuid = $1
+            ) q ON v.space = q.space AND cardinality(v.vector) = cardinality(q.vector)
+            WHERE v.upload_uuid <> $1 AND v.norm > 0 AND q.norm > 0
+                AND (u.user_uuid = $4 OR ($2 AND u.shared))
+            ORDER BY similarity DESC, u.added DESC
+            LIMIT $3"#
+        )
+        .bind(upload_uuid)
+        .bind(scope == SimilarityScope::Library)
+        .bind(k)
+        .bind(user_uuid)
+        .fetch_all(&get_pool().await)
+        .await
+        .map_err(|e| to_sql_error(upload_uuid, e))
+    }
+
+    /// Shares or unshares an upload, `false` if it doesn't belong to the user.
+    pub async fn set_upload_shared(upload_uuid: &str, user_uuid: &str, shared: bool) -> Result<bool, SqlError> {
+        let updated = sqlx::query("UPDATE uploads SET shared = $3 where upload_uuid = $1 and user_uuid = $2")
+            .bind(upload_uuid)
+            .bind(user_uuid)
+            .bind(shared)
+            .execute(&get_pool().await)
+            .await
+            .map_err(|e| to_sql_error(upload_uuid, e))?;
+
+        Ok(updated.rows_affected() > 0)
+    }
+
+    fn to_sql_error(upload_uuid: &str, e: sqlx::Error) -> SqlError {
+        SqlError::UploadQueryError(format!("Similar tracks couldn't be queried. {} \n {}", upload_uuid, e))
+    }
+}
+
+
+#[allow(unused)]
+pub mod segments {
+
+    use sqlx::{prelude::FromRow, types::Json};
+
+    use crate::db::db_conn::{get_pool, SqlError};
+    use crate::ml::ensemble::Ensemble;
+    use crate::ml::ml::MissingFeature;
+    use crate::ml::segments::{Segment, SegmentAnalysis, SegmentFeature};
+
+    /// Unfinished analyses that stored no segment for this long are taken to have been interrupted,
+    /// e.g. by a restart.
+    pub const ABANDONED_AFTER_MINUTES: i32 = 60;
+
+    #[derive(FromRow, Debug)]
+    pub struct StoredSegmentAnalysis {
+        pub class_labels: Vec<String>,
+        pub strategy: String,
+        pub segment_count: i32,
+        pub finished: bool,
+        pub abandoned: bool,
+        pub error: Option<String>,
+    }
+
+    #[derive(FromRow, Debug)]
+    pub struct StoredSegment {
+        pub segment_index: i32,
+        pub start_seconds: f32,
+        pub end_seconds: f32,
+        pub cum_classification: Vec<f32>,
+        pub features: Json<Vec<SegmentFeature>>,
+        pub missing_features: Json<Vec<MissingFeature>>,
+    }
+
+    /// Replaces any previous analysis of the upload, `false` while another one is still running.
+    pub async fn start_segment_analysis(
+        upload_uuid: &str,
+        class_labels: &[String],
+        strategy: Ensemble,
+        segment_count: usize,
+    ) -> Result<bool, SqlError> {
+        let pool = get_pool().await;
+        let mut tx = pool.begin().await.expect("should create transaction");
+
+        let started = sqlx::query(
+            "INSERT INTO segment_analyses (upload_uuid, class_labels, strategy, segment_count, started_at, updated_at) values ($1, $2, $3, $4, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP) ON CONFLICT (upload_uuid) DO UPDATE SET class_labels = EXCLUDED.class_labels, strategy = EXCLUDED.strategy, segment_count = EXCLUDED.segment_count, started_at = EXCLUDED.started_at, updated_at = EXCLUDED.updated_at, finished_at = NULL, error = NULL WHERE segment_analyses.finished_at IS NOT NULL OR segment_analyses.updated_at < CURRENT_TIMESTAMP - make_interval(mins => $5)"
+        )
+        .bind(upload_uuid)
+        .bind(class_labels)
+        .bind(strategy.key())
+        .bind(segment_count as i32)
+        .bind(ABANDONED_AFTER_MINUTES)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| to_sql_error(upload_uuid, e))?;
+
+        if started.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query("DELETE FROM segment_classifications where upload_uuid = $1")
+            .bind(upload_uuid)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| to_sql_error(upload_uuid, e))?;
+
+        tx.commit().await.map_err(|e| to_sql_error(upload_uuid, e))?;
+
+        Ok(true)
+    }
+
+    /// Stores the segment and bumps the analysis' `updated_at`, so it isn't taken as abandoned.
+    pub async fn save_segment(upload_uuid: &str, segment: &Segment) -> Result<(), SqlError> {
+        let pool = get_pool().await;
+        let mut tx = pool.begin().await.expect("should create transaction");
+
+        sqlx::query(
+            "INSERT INTO segment_classifications (upload_uuid, segment_index, start_seconds, end_seconds, cum_classification, major_class, features, missing_features) values ($1, $2, $3, $4, $5, $6, $7, $8)"
+        )
+        .bind(upload_uuid)
+        .bind(segment.index as i32)
+        .bind(segment.start_seconds)
+        .bind(segment.end_seconds)
+        .bind(&segment.cum_classification)
+        .bind(segment.major_class.as_ref().map(|class| class.label.clone()))
+        .bind(Json(&segment.features))
+        .bind(Json(&segment.missing_features))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| to_sql_error(upload_uuid, e))?;
+
+        sqlx::query("UPDATE segment_analyses SET updated_at = CURRENT_TIMESTAMP where upload_uuid = $1")
+            .bind(upload_uuid)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| to_sql_error(upload_uuid, e))?;
+
+        tx.commit().await.map_err(|e| to_sql_error(upload_uuid, e))?;
+
+        Ok(())
+    }
+
+    /// Marks the analysis as done, with the reason when it stopped early.
+    pub async fn finish_segment_analysis(upload_uuid: &str, error: Option<String>) -> Result<(), SqlError> {
+        sqlx::query("UPDATE segment_analyses SET finished_at = CURRENT_TIMESTAMP, error = $2 where upload_uuid = $1")
+            .bind(upload_uuid)
+            .bind(error)
+            .execute(&get_pool().await)
+            .await
+            .map_err(|e| to_sql_error(upload_uuid, e))?;
+
+        Ok(())
+    }
+
+    /// The analysis with the segments stored so far, `None` if the upload was never analysed.
+    pub async fn get_segment_analysis(upload_uuid: &str) -> Result<Option<SegmentAnalysis>, SqlError> {
+        let pool = get_pool().await;
+
+        let analysis = sqlx::query_as::<_, StoredSegmentAnalysis>(
+            "SELECT class_labels, strategy, segment_count, finished_at IS NOT NULL AS finished, updated_at < CURRENT_TIMESTAMP - make_interval(mins => $2) AS abandoned, error from segment_analyses where upload_uuid = $1"
+        )
+        .bind(upload_uuid)
+        .bind(ABANDONED_AFTER_MINUTES)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| to_sql_error(upload_uuid, e))?;
+
+        let Some(analysis) = analysis else {
+            return Ok(None);
+        };
+
+        let segments = sqlx::query_as::<_, StoredSegment>(
+            "SELECT segment_index, start_seconds, end_seconds, cum_classification, features, missing_features from segment_classifications where upload_uuid = $1 ORDER BY segment_index"
+        )
+        .bind(upload_uuid)
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| to_sql_error(upload_uuid, e))?
+        .into_iter()
+        .map(|stored| {
+            Segment::new(
+                stored.segment_index as usize,
+                stored.start_seconds,
+                stored.end_seconds,
+                &analysis.class_labels,
+                stored.cum_classification,
+                stored.features.0,
+                stored.missing_features.0,
+            )
+        })
+        .collect();
+
+        let strategy: Ensemble = analysis.strategy.parse().map_err(SqlError::UploadQueryError)?;
+
+        let (finished, error) = match (analysis.finished, analysis.abandoned) {
+            (false, true) => (true, Some("The analysis was interrupted, start it again".to_string())),
+            _ => (analysis.finished, analysis.error),
+        };
+
+        Ok(Some(SegmentAnalysis::new(
+            analysis.class_labels,
+            strategy,
+            analysis.segment_count as usize,
+            segments,
+            finished,
+            error,
+        )))
+    }
+
+    fn to_sql_error(upload_uuid: &str, e: sqlx::Error) -> SqlError {
+        SqlError::UploadQueryError(format!("Segment analysis couldn't be stored or read. {} \n {}", upload_uuid, e))
+    }
+}
\ No newline at end of file
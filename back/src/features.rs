@@ -0,0 +1,1208 @@
+//! Native versions of the backend-etl transforms in `data/server_utils/artifacts_gen.py`, so an
+//! upload can be turned into the nine model inputs without the Python service. Each function
+//! names the librosa call it mirrors, with the parameters the ETL passes.
+
+use std::{
+    collections::HashMap,
+    env, fmt,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use ndarray::{Array1, Array2, Array3, Axis};
+use ndarray_npy::{ReadNpyExt, WriteNpyExt};
+use realfft::RealFftPlanner;
+
+use crate::features::{
+    chroma::{cens, chroma_filters, cq_to_chroma, estimate_tuning, normalize_columns, tonnetz, Norm},
+    cqt::ConstantQ,
+    spectral::{amplitude_to_db, dct_matrix, hann_symmetric, max, mel_filters, power_to_db, Stft, TOP_DB},
+};
+use crate::ml::ml::{find_signal_path_in, Feature, FRAME_HOP, FRAME_LENGTH, SAMPLE_RATE};
+
+/// Length of the excerpt cut from the middle of every upload.
+pub const SAMPLE_SECONDS: usize = 30;
+
+const N_FFT: usize = 2048;
+const HOP: usize = 256;
+const MEL_N_FFT: usize = 8192;
+const N_MELS: usize = 1025;
+const MFCC_N_MELS: usize = 128;
+const N_MFCC: usize = 12;
+const N_CHROMA: usize = 12;
+const CQT_HOP: usize = 512;
+const CQT_BINS_PER_OCTAVE: usize = 36;
+const CQT_OCTAVES: usize = 7;
+
+#[derive(Debug)]
+pub enum FeatureError {
+    /// Length of the signal in samples.
+    TooShort(usize),
+    Silent,
+    NoFrames,
+    Stats(String),
+    Io(String),
+}
+
+impl fmt::Display for FeatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeatureError::TooShort(samples) => write!(
+                f,
+                "Signal of {:.1} s isn't longer than {} s",
+                *samples as f32 / SAMPLE_RATE as f32,
+                SAMPLE_SECONDS
+            ),
+            FeatureError::Silent => write!(f, "Signal is silent"),
+            FeatureError::NoFrames => write!(f, "No frames to transform"),
+            FeatureError::Stats(e) => write!(f, "Normalization stats couldn't be loaded: {}", e),
+            FeatureError::Io(e) => write!(f, "Features couldn't be written: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FeatureError {}
+
+/// Middle `SAMPLE_SECONDS` of the signal with half a second Hann fade in and out, scaled to a
+/// peak of 1, `extract_y_middle` in the ETL.
+pub fn middle_sample(signal: &[f32]) -> Result<Vec<f32>, FeatureError> {
+    let sr = SAMPLE_RATE as usize;
+
+    if signal.len() <= SAMPLE_SECONDS * sr {
+        return Err(FeatureError::TooShort(signal.len()));
+    }
+
+    let mid = signal.len() / 2;
+    let half = SAMPLE_SECONDS / 2 * sr;
+
+    prepare_sample(&signal[mid - half..mid + half])
+}
+
+/// Fades any window in and out and scales it to a peak of 1, as `middle_sample` does with the
+/// middle one.
+pub fn prepare_sample(window: &[f32]) -> Result<Vec<f32>, FeatureError> {
+    let sr = SAMPLE_RATE as usize;
+    let mut sample = window.to_vec();
+
+    // shorter than a single frame
+    if sample.len() < sr {
+        return Err(FeatureError::NoFrames);
+    }
+
+    // get_hanned: the rising half of a one second window on the start, the falling half on the end
+    let window = hann_symmetric(sr);
+    let rise = sr / 2;
+    let fall = sr - rise;
+    let len = sample.len();
+
+    for i in 0..rise {
+        sample[i] = (sample[i] as f64 * window[i]) as f32;
+    }
+    for i in 0..fall {
+        let j = len - fall + i;
+        sample[j] = (sample[j] as f64 * window[rise + i]) as f32;
+    }
+
+    let peak = sample.iter().fold(0.0f32, |a, b| a.max(b.abs()));
+    if peak == 0.0 {
+        return Err(FeatureError::Silent);
+    }
+    sample.iter_mut().for_each(|v| *v /= peak);
+
+    Ok(sample)
+}
+
+/// `(frames, FRAME_LENGTH)` windows every `FRAME_HOP` samples, `librosa.util.frame(...).T`.
+pub fn split_to_frames(sample: &[f32]) -> Array2<f32> {
+    let (length, hop) = (FRAME_LENGTH as usize, FRAME_HOP as usize);
+    let n_frames = if sample.len() < length { 0 } else { 1 + (sample.len() - length) / hop };
+
+    Array2::from_shape_fn((n_frames, length), |(i, j)| sample[i * hop + j])
+}
+
+/// Runs the ETL pipeline on a decoded mono `SAMPLE_RATE` signal and writes every feature into
+/// `features_path`, laid out like `SERVER_DATA/features/<upload>/`.
+pub fn extract_to(
+    signal: &[f32],
+    features_path: &Path,
+    stats: &NormalizationStats,
+    extractor: &mut FeatureExtractor,
+) -> Result<(), FeatureError> {
+    let frames = split_to_frames(&middle_sample(signal)?);
+
+    extractor.transform_all(&frames, stats, |feature, values| {
+        tracing::info!("{:?} extracted, shape {:?}", feature, values.shape());
+        write_feature(&find_signal_path_in(features_path, &feature), &values)
+    })
+}
+
+fn write_feature(path: &Path, values: &Array3<f32>) -> Result<(), FeatureError> {
+    let io_error = |e: String| FeatureError::Io(format!("{}: {}", path.display(), e));
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| io_error(e.to_string()))?;
+    }
+
+    let file = File::create(path).map_err(|e| io_error(e.to_string()))?;
+    values.write_npy(BufWriter::new(file)).map_err(|e| io_error(e.to_string()))
+}
+
+/// Per-column mean and std the models were trained with: `<name>_mean.npy` and `<name>_std.npy`
+/// of shape `(1, 1, columns)` in the ETL's metadata directory.
+pub struct NormalizationStats {
+    dir: PathBuf,
+}
+
+impl NormalizationStats {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self { dir: dir.as_ref().to_path_buf() }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(env::var("METADATA").unwrap_or("/metadata".to_string()))
+    }
+
+    pub fn column_stats(&self, name: &str) -> Result<(Array1<f32>, Array1<f32>), FeatureError> {
+        let read = |suffix: &str| {
+            let path = self.dir.join(format!("{}_{}.npy", name, suffix));
+            let file = File::open(&path).map_err(|e| FeatureError::Stats(format!("{}: {}", path.display(), e)))?;
+            let stats = Array3::<f32>::read_npy(file)
+                .map_err(|e| FeatureError::Stats(format!("{}: {}", path.display(), e)))?;
+
+            Ok(Array1::from_iter(stats.iter().copied()))
+        };
+
+        Ok((read("mean")?, read("std")?))
+    }
+
+    /// `(x - mean) / std` per time column.
+    fn standardize(&self, name: &str, values: &mut Array3<f32>) -> Result<(), FeatureError> {
+        let (mean, std) = self.column_stats(name)?;
+        standardize(values, &mean, &std)
+    }
+}
+
+fn standardize(values: &mut Array3<f32>, mean: &Array1<f32>, std: &Array1<f32>) -> Result<(), FeatureError> {
+    let columns = values.shape()[2];
+    if mean.len() != columns || std.len() != columns {
+        return Err(FeatureError::Stats(format!(
+            "{} mean and {} std values for {} columns",
+            mean.len(),
+            std.len(),
+            columns
+        )));
+    }
+
+    for mut lane in values.lanes_mut(Axis(2)) {
+        lane -= mean;
+        lane /= std;
+    }
+
+    Ok(())
+}
+
+/// The ETL only standardizes MFCC, chroma and tonnetz when some value leaves (-1, 1).
+fn leaves_unit_range(values: &Array3<f32>) -> bool {
+    values.iter().any(|v| *v <= -1.0 || *v >= 1.0)
+}
+
+fn stack_frames(per_frame: &[Array2<f32>]) -> Result<Array3<f32>, FeatureError> {
+    let views: Vec<_> = per_frame.iter().map(|frame| frame.view()).collect();
+    ndarray::stack(Axis(0), &views).map_err(|_| FeatureError::NoFrames)
+}
+
+/// FFT plans and filter banks reused across frames and tracks. Chroma filters and CQT kernels
+/// depend on the tuning estimated for each frame, so they're cached per tuning.
+pub struct FeatureExtractor {
+    planner: RealFftPlanner<f32>,
+    stft: Stft,
+    mel_stft: Stft,
+    tuning_stft: Stft,
+    mel_filters: Array2<f32>,
+    mfcc_filters: Array2<f32>,
+    dct: Array2<f32>,
+    /// Keyed by tuning in hundredths of a bin.
+    chroma_filters: HashMap<i32, Array2<f32>>,
+    constant_q: HashMap<i32, ConstantQ>,
+}
+
+impl FeatureExtractor {
+    pub fn new() -> Self {
+        let sr = SAMPLE_RATE as f64;
+        let mut planner = RealFftPlanner::new();
+
+        Self {
+            stft: Stft::hann(&mut planner, N_FFT, HOP),
+            mel_stft: Stft::hann(&mut planner, MEL_N_FFT, HOP),
+            tuning_stft: Stft::hann(&mut planner, N_FFT, N_FFT / 4),
+            mel_filters: mel_filters(sr, MEL_N_FFT, N_MELS),
+            mfcc_filters: mel_filters(sr, N_FFT, MFCC_N_MELS),
+            dct: dct_matrix(MFCC_N_MELS, N_MFCC),
+            chroma_filters: HashMap::new(),
+            constant_q: HashMap::new(),
+            planner,
+        }
+    }
+
+    /// One feature for every frame of `frames`, `(frames, a, b)` and standardized like the ETL
+    /// writes it.
+    pub fn transform(
+        &mut self,
+        feature: &Feature,
+        frames: &Array2<f32>,
+        stats: &NormalizationStats,
+    ) -> Result<Array3<f32>, FeatureError> {
+        match feature {
+            Feature::ChromaCqt | Feature::ChromaCens | Feature::Tonnetz => {
+                let chroma = self.cqt_chroma(frames);
+                cqt_feature(feature, &chroma, stats)
+            }
+            _ => self.stft_feature(feature, frames, stats),
+        }
+    }
+
+    /// Every feature, handed to `sink` one at a time so only one is held in memory. The CQT is
+    /// computed once for the three chroma CQT based features.
+    pub fn transform_all<F>(
+        &mut self,
+        frames: &Array2<f32>,
+        stats: &NormalizationStats,
+        mut sink: F,
+    ) -> Result<(), FeatureError>
+    where
+        F: FnMut(Feature, Array3<f32>) -> Result<(), FeatureError>,
+    {
+        for feature in [
+            Feature::Ft,
+            Feature::Spectrogram,
+            Feature::MelSpectrogram,
+            Feature::PowerSpectrogram,
+            Feature::Mfcc,
+            Feature::ChromaStft,
+        ] {
+            let values = self.stft_feature(&feature, frames, stats)?;
+            sink(feature, values)?;
+        }
+
+        let chroma = self.cqt_chroma(frames);
+        for feature in [Feature::ChromaCens, Feature::ChromaCqt, Feature::Tonnetz] {
+            let values = cqt_feature(&feature, &chroma, stats)?;
+            sink(feature, values)?;
+        }
+
+        Ok(())
+    }
+
+    fn stft_feature(
+        &mut self,
+        feature: &Feature,
+        frames: &Array2<f32>,
+        stats: &NormalizationStats,
+    ) -> Result<Array3<f32>, FeatureError> {
+        let per_frame: Vec<Array2<f32>> = frames
+            .outer_iter()
+            .map(|frame| self.stft_frame(feature, &frame.to_vec()))
+            .collect();
+        let mut values = stack_frames(&per_frame)?;
+
+        match feature {
+            Feature::Ft => {
+                values.mapv_inplace(f32::ln_1p);
+                let (_, std) = stats.column_stats("ft")?;
+
+                // like the ETL, the mean comes from this track rather than ft_mean.npy
+                let (frames, bins, columns) = values.dim();
+                let mean = Array1::from_shape_fn(columns, |t| {
+                    let sum: f64 = values.slice(ndarray::s![.., .., t]).iter().map(|v| *v as f64).sum();
+                    (sum / (frames * bins) as f64) as f32
+                });
+
+                standardize(&mut values, &mean, &std.mapv(|s| s + 1e-8))?;
+            }
+            Feature::Spectrogram => stats.standardize("spec", &mut values)?,
+            Feature::MelSpectrogram => stats.standardize("mel_spec", &mut values)?,
+            Feature::PowerSpectrogram => stats.standardize("power_spec", &mut values)?,
+            Feature::Mfcc if leaves_unit_range(&values) => stats.standardize("mfcc", &mut values)?,
+            Feature::ChromaStft if leaves_unit_range(&values) => stats.standardize("chroma_stft", &mut values)?,
+            _ => {}
+        }
+
+        Ok(values)
+    }
+
+    fn stft_frame(&mut self, feature: &Feature, frame: &[f32]) -> Array2<f32> {
+        match feature {
+            Feature::Ft => self.stft.magnitude(frame),
+            Feature::Spectrogram => amplitude_to_db(&self.stft.magnitude(frame)),
+            Feature::PowerSpectrogram => {
+                let power = self.stft.power(frame);
+                power_to_db(&power, max(&power), Some(TOP_DB))
+            }
+            Feature::MelSpectrogram => {
+                let mel = self.mel_filters.dot(&self.mel_stft.power(frame));
+                power_to_db(&mel, max(&mel), Some(TOP_DB))
+            }
+            Feature::Mfcc => {
+                let mel = self.mfcc_filters.dot(&self.stft.power(frame));
+                self.dct.dot(&power_to_db(&mel, 1.0, Some(TOP_DB)))
+            }
+            Feature::ChromaStft => {
+                let power = self.stft.power(frame);
+                let tuning = estimate_tuning(&power, SAMPLE_RATE as f64, N_FFT, N_CHROMA);
+
+                let filters = self
+                    .chroma_filters
+                    .entry(tuning_key(tuning))
+                    .or_insert_with(|| chroma_filters(SAMPLE_RATE as f64, N_FFT, N_CHROMA, tuning));
+
+                let mut chroma = filters.dot(&power);
+                normalize_columns(&mut chroma, Norm::Max);
+                chroma
+            }
+            Feature::ChromaCqt | Feature::ChromaCens | Feature::Tonnetz => {
+                unreachable!("CQT based features don't come from the STFT")
+            }
+        }
+    }
+
+    /// Unnormalized 12 bin chroma of the CQT of each frame, `chroma_cqt(y, sr, norm=None)`.
+    fn cqt_chroma(&mut self, frames: &Array2<f32>) -> Vec<Array2<f32>> {
+        let sr = SAMPLE_RATE as f64;
+        // C1
+        let fmin = 440.0 * 2f64.powf((24.0 - 69.0) / 12.0);
+
+        frames
+            .outer_iter()
+            .map(|frame| {
+                let frame = frame.to_vec();
+                let tuning = estimate_tuning(&self.tuning_stft.magnitude(&frame), sr, N_FFT, CQT_BINS_PER_OCTAVE);
+
+                let planner = &mut self.planner;
+                let constant_q = self.constant_q.entry(tuning_key(tuning)).or_insert_with(|| {
+                    ConstantQ::new(
+                        planner,
+                        sr,
+                        CQT_HOP,
+                        fmin,
+                        CQT_OCTAVES * CQT_BINS_PER_OCTAVE,
+                        CQT_BINS_PER_OCTAVE,
+                        tuning,
+                    )
+                });
+
+                cq_to_chroma(&constant_q.magnitude(&frame), CQT_BINS_PER_OCTAVE, N_CHROMA)
+            })
+            .collect()
+    }
+}
+
+impl Default for FeatureExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn tuning_key(tuning: f64) -> i32 {
+    (tuning * 100.0).round() as i32
+}
+
+fn cqt_feature(
+    feature: &Feature,
+    chroma: &[Array2<f32>],
+    stats: &NormalizationStats,
+) -> Result<Array3<f32>, FeatureError> {
+    let per_frame: Vec<Array2<f32>> = chroma
+        .iter()
+        .map(|chroma| match feature {
+            Feature::ChromaCens => cens(chroma),
+            Feature::Tonnetz => tonnetz(chroma),
+            _ => {
+                let mut chroma = chroma.clone();
+                normalize_columns(&mut chroma, Norm::Max);
+                chroma
+            }
+        })
+        .collect();
+    let mut values = stack_frames(&per_frame)?;
+
+    if leaves_unit_range(&values) {
+        let name = match feature {
+            Feature::ChromaCens => "chroma_cens",
+            Feature::Tonnetz => "tonnetz",
+            _ => "chroma_cqt",
+        };
+        stats.standardize(name, &mut values)?;
+    }
+
+    Ok(values)
+}
+
+pub mod spectral {
+
+    use std::{f64::consts::PI, sync::Arc};
+
+    use ndarray::Array2;
+    use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+    /// Silence floor of `power_to_db`, the square of `amplitude_to_db`'s.
+    pub const AMIN: f32 = 1e-10;
+    /// Everything more than this many dB below the loudest bin is clipped.
+    pub const TOP_DB: f32 = 80.0;
+
+    /// Periodic Hann window, `scipy.signal.get_window("hann", n)` as used by `librosa.stft`.
+    pub fn hann(n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / n as f64).cos())
+            .collect()
+    }
+
+    /// Symmetric Hann window, `np.hanning(n)`.
+    pub fn hann_symmetric(n: usize) -> Vec<f64> {
+        if n == 1 {
+            return vec![1.0];
+        }
+
+        (0..n)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / (n - 1) as f64).cos())
+            .collect()
+    }
+
+    pub fn max(values: &Array2<f32>) -> f32 {
+        values.fold(f32::NEG_INFINITY, |a, b| a.max(*b))
+    }
+
+    /// Centered, zero padded STFT, `librosa.stft(y, n_fft, hop_length, window, center=True)`.
+    pub struct Stft {
+        n_fft: usize,
+        hop: usize,
+        window: Vec<f32>,
+        fft: Arc<dyn RealToComplex<f32>>,
+    }
+
+    impl Stft {
+        pub fn new(planner: &mut RealFftPlanner<f32>, n_fft: usize, hop: usize, window: Vec<f64>) -> Self {
+            Self {
+                n_fft,
+                hop,
+                window: window.into_iter().map(|w| w as f32).collect(),
+                fft: planner.plan_fft_forward(n_fft),
+            }
+        }
+
+        pub fn hann(planner: &mut RealFftPlanner<f32>, n_fft: usize, hop: usize) -> Self {
+            Self::new(planner, n_fft, hop, hann(n_fft))
+        }
+
+        /// Rectangular window, as `librosa.cqt` frames its input.
+        pub fn rectangular(planner: &mut RealFftPlanner<f32>, n_fft: usize, hop: usize) -> Self {
+            Self::new(planner, n_fft, hop, vec![1.0; n_fft])
+        }
+
+        pub fn n_frames(&self, len: usize) -> usize {
+            1 + len / self.hop
+        }
+
+        /// Calls `f` with the index and the `1 + n_fft / 2` bins of every frame.
+        pub fn for_each_frame<F: FnMut(usize, &[Complex<f32>])>(&self, signal: &[f32], mut f: F) {
+            let mut input = self.fft.make_input_vec();
+            let mut output = self.fft.make_output_vec();
+            let pad = self.n_fft / 2;
+
+            for t in 0..self.n_frames(signal.len()) {
+                for (k, sample) in input.iter_mut().enumerate() {
+                    *sample = match (t * self.hop + k).checked_sub(pad).and_then(|i| signal.get(i)) {
+                        Some(value) => value * self.window[k],
+                        None => 0.0,
+                    };
+                }
+
+                self.fft
+                    .process(&mut input, &mut output)
+                    .expect("FFT buffers come from the plan");
+                f(t, &output);
+            }
+        }
+
+        /// `(1 + n_fft / 2, 1 + len / hop)` magnitudes.
+        pub fn magnitude(&self, signal: &[f32]) -> Array2<f32> {
+            self.map_bins(signal, |bin| bin.norm())
+        }
+
+        /// `(1 + n_fft / 2, 1 + len / hop)` squared magnitudes.
+        pub fn power(&self, signal: &[f32]) -> Array2<f32> {
+            self.map_bins(signal, |bin| bin.norm_sqr())
+        }
+
+        fn map_bins<F: Fn(&Complex<f32>) -> f32>(&self, signal: &[f32], f: F) -> Array2<f32> {
+            let mut values = Array2::zeros((self.n_fft / 2 + 1, self.n_frames(signal.len())));
+
+            self.for_each_frame(signal, |t, bins| {
+                for (value, bin) in values.column_mut(t).iter_mut().zip(bins) {
+                    *value = f(bin);
+                }
+            });
+
+            values
+        }
+    }
+
+    /// `librosa.power_to_db(power, ref=reference, amin=AMIN, top_db)`.
+    pub fn power_to_db(power: &Array2<f32>, reference: f32, top_db: Option<f32>) -> Array2<f32> {
+        let offset = 10.0 * reference.max(AMIN).log10();
+        let mut db = power.mapv(|p| 10.0 * p.max(AMIN).log10() - offset);
+
+        if let Some(top_db) = top_db {
+            let floor = max(&db) - top_db;
+            db.mapv_inplace(|v| v.max(floor));
+        }
+
+        db
+    }
+
+    /// `librosa.amplitude_to_db(magnitude, ref=np.max)`.
+    pub fn amplitude_to_db(magnitude: &Array2<f32>) -> Array2<f32> {
+        let reference = max(magnitude);
+        power_to_db(&magnitude.mapv(|m| m * m), reference * reference, Some(TOP_DB))
+    }
+
+    const MEL_F_SP: f64 = 200.0 / 3.0;
+    const MEL_MIN_LOG_HZ: f64 = 1000.0;
+    const MEL_MIN_LOG_MEL: f64 = MEL_MIN_LOG_HZ / MEL_F_SP;
+
+    fn mel_log_step() -> f64 {
+        6.4f64.ln() / 27.0
+    }
+
+    /// Slaney mel scale: linear below 1 kHz, logarithmic above.
+    pub fn hz_to_mel(frequency: f64) -> f64 {
+        if frequency >= MEL_MIN_LOG_HZ {
+            MEL_MIN_LOG_MEL + (frequency / MEL_MIN_LOG_HZ).ln() / mel_log_step()
+        } else {
+            frequency / MEL_F_SP
+        }
+    }
+
+    pub fn mel_to_hz(mel: f64) -> f64 {
+        if mel >= MEL_MIN_LOG_MEL {
+            MEL_MIN_LOG_HZ * (mel_log_step() * (mel - MEL_MIN_LOG_MEL)).exp()
+        } else {
+            MEL_F_SP * mel
+        }
+    }
+
+    /// `librosa.filters.mel(sr, n_fft, n_mels)` from 0 Hz to Nyquist with Slaney area
+    /// normalization, `(n_mels, 1 + n_fft / 2)`.
+    pub fn mel_filters(sr: f64, n_fft: usize, n_mels: usize) -> Array2<f32> {
+        let fft_frequencies: Vec<f64> = (0..=n_fft / 2).map(|i| i as f64 * sr / n_fft as f64).collect();
+
+        let max_mel = hz_to_mel(sr / 2.0);
+        let mel_frequencies: Vec<f64> = (0..n_mels + 2)
+            .map(|i| match i {
+                i if i == n_mels + 1 => mel_to_hz(max_mel),
+                i => mel_to_hz(i as f64 * max_mel / (n_mels + 1) as f64),
+            })
+            .collect();
+
+        Array2::from_shape_fn((n_mels, fft_frequencies.len()), |(i, j)| {
+            let (left, centre, right) = (mel_frequencies[i], mel_frequencies[i + 1], mel_frequencies[i + 2]);
+
+            let lower = (fft_frequencies[j] - left) / (centre - left);
+            let upper = (right - fft_frequencies[j]) / (right - centre);
+            let area = 2.0 / (right - left);
+
+            (lower.min(upper).max(0.0) * area) as f32
+        })
+    }
+
+    /// Orthonormal DCT-II as an `(n_out, n_in)` matrix, the first `n_out` rows of
+    /// `scipy.fftpack.dct(x, type=2, norm="ortho", axis=0)`.
+    pub fn dct_matrix(n_in: usize, n_out: usize) -> Array2<f32> {
+        let n = n_in as f64;
+
+        Array2::from_shape_fn((n_out, n_in), |(k, i)| {
+            let scale = if k == 0 { (1.0 / n).sqrt() } else { (2.0 / n).sqrt() };
+            (scale * (PI * k as f64 * (2 * i + 1) as f64 / (2.0 * n)).cos()) as f32
+        })
+    }
+}
+
+pub mod chroma {
+
+    use std::f64::consts::PI;
+
+    use ndarray::Array2;
+
+    use crate::features::spectral::hann_symmetric;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Norm {
+        Max,
+        L1,
+        L2,
+    }
+
+    /// `librosa.util.normalize(x, norm, axis=0)`. Columns whose norm is below the smallest
+    /// positive f32 are left as they are.
+    pub fn normalize_columns(values: &mut Array2<f32>, norm: Norm) {
+        for mut column in values.columns_mut() {
+            let length = match norm {
+                Norm::Max => column.fold(0.0f32, |a, b| a.max(b.abs())),
+                Norm::L1 => column.iter().map(|v| v.abs()).sum(),
+                Norm::L2 => column.iter().map(|v| v * v).sum::<f32>().sqrt(),
+            };
+
+            if length >= f32::MIN_POSITIVE {
+                column.mapv_inplace(|v| v / length);
+            }
+        }
+    }
+
+    /// `librosa.hz_to_octs`: octaves above A440 / 16 with A440 moved by `tuning` bins.
+    pub fn hz_to_octs(frequency: f64, tuning: f64, bins_per_octave: usize) -> f64 {
+        let a440 = 440.0 * 2f64.powf(tuning / bins_per_octave as f64);
+        (frequency / (a440 / 16.0)).log2()
+    }
+
+    /// `librosa.piptrack(S=spectrogram, sr, n_fft)`: interpolated frequency and magnitude of
+    /// every local peak between 150 Hz and 4 kHz that reaches a tenth of its frame's maximum.
+    pub fn piptrack(spectrogram: &Array2<f32>, sr: f64, n_fft: usize) -> Vec<(f32, f32)> {
+        let (fmin, fmax) = (150.0, 4000.0f64.min(sr / 2.0));
+        let n_bins = spectrogram.nrows();
+        let mut peaks = Vec::new();
+
+        for column in spectrogram.columns() {
+            let reference = 0.1 * column.fold(f32::NEG_INFINITY, |a, b| a.max(*b));
+            let masked = |i: usize| if column[i] > reference { column[i] } else { 0.0 };
+
+            // the first and last bins are outside the frequency range
+            for i in 1..n_bins - 1 {
+                let frequency = i as f64 * sr / n_fft as f64;
+                if frequency < fmin || frequency >= fmax {
+                    continue;
+                }
+                if !(masked(i) > masked(i - 1) && masked(i) >= masked(i + 1)) {
+                    continue;
+                }
+
+                let avg = 0.5 * (column[i + 1] - column[i - 1]);
+                let curvature = 2.0 * column[i] - column[i + 1] - column[i - 1];
+                let shift = avg / (curvature + if curvature.abs() < f32::MIN_POSITIVE { 1.0 } else { 0.0 });
+
+                let pitch = ((i as f64 + shift as f64) * sr / n_fft as f64) as f32;
+                peaks.push((pitch, column[i] + 0.5 * avg * shift));
+            }
+        }
+
+        peaks
+    }
+
+    /// `librosa.estimate_tuning(S=spectrogram, sr, n_fft, bins_per_octave)`: the tuning of the
+    /// peaks at least as strong as their median, in fractions of a bin.
+    pub fn estimate_tuning(spectrogram: &Array2<f32>, sr: f64, n_fft: usize, bins_per_octave: usize) -> f64 {
+        let peaks: Vec<(f32, f32)> = piptrack(spectrogram, sr, n_fft)
+            .into_iter()
+            .filter(|(pitch, _)| *pitch > 0.0)
+            .collect();
+
+        let mut magnitudes: Vec<f32> = peaks.iter().map(|(_, magnitude)| *magnitude).collect();
+        magnitudes.sort_by(|a, b| a.total_cmp(b));
+        let threshold = match magnitudes.len() {
+            0 => 0.0,
+            n if n % 2 == 1 => magnitudes[n / 2],
+            n => (magnitudes[n / 2 - 1] + magnitudes[n / 2]) / 2.0,
+        };
+
+        let pitches: Vec<f32> = peaks
+            .iter()
+            .filter(|(_, magnitude)| *magnitude >= threshold)
+            .map(|(pitch, _)| *pitch)
+            .collect();
+
+        pitch_tuning(&pitches, bins_per_octave)
+    }
+
+    /// `librosa.pitch_tuning(frequencies, resolution=0.01, bins_per_octave)`: the left edge of
+    /// the most populated 0.01 bin wide histogram bin of deviations from the equal tempered grid.
+    pub fn pitch_tuning(frequencies: &[f32], bins_per_octave: usize) -> f64 {
+        const BINS: usize = 100;
+        // np.linspace(-0.5, 0.5, 101)
+        let edge = |i: usize| if i == BINS { 0.5 } else { i as f64 * (1.0 / BINS as f64) - 0.5 };
+
+        let mut counts = [0usize; BINS];
+        let mut any = false;
+
+        for frequency in frequencies.iter().filter(|f| **f > 0.0) {
+            any = true;
+
+            // computed in f32 like the ETL's float32 arrays
+            let octaves = (frequency / (440.0f32 / 16.0)).log2();
+            let mut residual = (bins_per_octave as f32 * octaves).rem_euclid(1.0);
+            if residual >= 0.5 {
+                residual -= 1.0;
+            }
+
+            let residual = residual as f64;
+            let mut bin = (((residual + 0.5) * BINS as f64).floor() as usize).min(BINS - 1);
+            while bin > 0 && residual < edge(bin) {
+                bin -= 1;
+            }
+            while bin < BINS - 1 && residual >= edge(bin + 1) {
+                bin += 1;
+            }
+
+            counts[bin] += 1;
+        }
+
+        if !any {
+            return 0.0;
+        }
+
+        let most_common = counts
+            .iter()
+            .enumerate()
+            .fold(0, |best, (i, count)| if *count > counts[best] { i } else { best });
+
+        edge(most_common)
+    }
+
+    /// `librosa.filters.chroma(sr, n_fft, n_chroma, tuning)`: Gaussian bumps around each pitch
+    /// class weighted towards octave 5, starting at C, `(n_chroma, 1 + n_fft / 2)`.
+    pub fn chroma_filters(sr: f64, n_fft: usize, n_chroma: usize, tuning: f64) -> Array2<f32> {
+        let chroma = n_chroma as f64;
+
+        let mut bins: Vec<f64> = (0..n_fft)
+            .map(|k| chroma * hz_to_octs(k as f64 * sr / n_fft as f64, tuning, n_chroma))
+            .collect();
+        // 0 Hz is a log of zero, put it an octave and a half below the first bin
+        bins[0] = bins[1] - 1.5 * chroma;
+
+        let widths: Vec<f64> = (0..n_fft)
+            .map(|k| if k + 1 < n_fft { (bins[k + 1] - bins[k]).max(1.0) } else { 1.0 })
+            .collect();
+
+        let half = (chroma / 2.0).round();
+        let roll = 3 * (n_chroma / 12);
+        let mut weights = Array2::<f32>::zeros((n_chroma, n_fft / 2 + 1));
+
+        for k in 0..=n_fft / 2 {
+            let column: Vec<f64> = (0..n_chroma)
+                .map(|c| {
+                    let distance = (bins[k] - c as f64 + half + 10.0 * chroma).rem_euclid(chroma) - half;
+                    (-0.5 * (2.0 * distance / widths[k]).powi(2)).exp()
+                })
+                .collect();
+
+            let norm = column.iter().map(|w| w * w).sum::<f64>().sqrt();
+            let norm = if norm < f64::MIN_POSITIVE { 1.0 } else { norm };
+            let octave_weight = (-0.5 * ((bins[k] / chroma - 5.0) / 2.0).powi(2)).exp();
+
+            for c in 0..n_chroma {
+                weights[[c, k]] = (column[(c + roll) % n_chroma] / norm * octave_weight) as f32;
+            }
+        }
+
+        weights
+    }
+
+    /// `librosa.filters.cq_to_chroma` summed over a CQT starting at C: every pitch class takes
+    /// its centre bin and the one on each side, in every octave.
+    pub fn cq_to_chroma(cqt: &Array2<f32>, bins_per_octave: usize, n_chroma: usize) -> Array2<f32> {
+        let merge = bins_per_octave / n_chroma;
+        let mut chroma = Array2::zeros((n_chroma, cqt.ncols()));
+
+        for (bin, row) in cqt.outer_iter().enumerate() {
+            let class = ((bin % bins_per_octave + merge / 2) / merge) % n_chroma;
+            let mut target = chroma.row_mut(class);
+            target += &row;
+        }
+
+        chroma
+    }
+
+    /// `librosa.feature.chroma_cens` from unnormalized chroma: L1 normalized, quantized,
+    /// smoothed over 41 frames and L2 normalized.
+    pub fn cens(chroma: &Array2<f32>) -> Array2<f32> {
+        const QUANT_STEPS: [f32; 4] = [0.4, 0.2, 0.1, 0.05];
+        const SMOOTHING: usize = 41;
+
+        let mut chroma = chroma.clone();
+        normalize_columns(&mut chroma, Norm::L1);
+
+        let quantized = chroma.mapv(|v| QUANT_STEPS.iter().filter(|step| v > **step).count() as f32 * 0.25);
+
+        let window = hann_symmetric(SMOOTHING + 2);
+        let total: f64 = window.iter().sum();
+        let centre = window.len() / 2;
+        let frames = quantized.ncols();
+
+        let mut smoothed = Array2::from_shape_fn(quantized.dim(), |(c, t)| {
+            let value: f64 = window
+                .iter()
+                .enumerate()
+                .filter_map(|(j, w)| {
+                    let source = (t + j).checked_sub(centre).filter(|s| *s < frames)?;
+                    Some(w / total * quantized[[c, source]] as f64)
+                })
+                .sum();
+            value as f32
+        });
+
+        normalize_columns(&mut smoothed, Norm::L2);
+        smoothed
+    }
+
+    /// `librosa.feature.tonnetz(chroma=...)`: the L1 normalized chroma projected on the fifths,
+    /// minor thirds and major thirds circles, `(6, frames)`.
+    pub fn tonnetz(chroma: &Array2<f32>) -> Array2<f32> {
+        const SCALE: [f64; 6] = [7.0 / 6.0, 7.0 / 6.0, 3.0 / 2.0, 3.0 / 2.0, 2.0 / 3.0, 2.0 / 3.0];
+        const RADIUS: [f64; 6] = [1.0, 1.0, 1.0, 1.0, 0.5, 0.5];
+
+        let n_chroma = chroma.nrows();
+        let phi = Array2::from_shape_fn((6, n_chroma), |(p, c)| {
+            let mut v = SCALE[p] * (c as f64 * 12.0 / n_chroma as f64);
+            if p % 2 == 0 {
+                v -= 0.5;
+            }
+            RADIUS[p] * (PI * v).cos()
+        });
+
+        let mut chroma = chroma.clone();
+        normalize_columns(&mut chroma, Norm::L1);
+
+        phi.dot(&chroma.mapv(|v| v as f64)).mapv(|v| v as f32)
+    }
+}
+
+pub mod cqt {
+
+    use std::f64::consts::PI;
+
+    use ndarray::Array2;
+    use realfft::{num_complex::Complex, RealFftPlanner};
+
+    use crate::features::spectral::{hann, Stft};
+
+    /// Share of each filter's spectral magnitude dropped as negligible, `sparsity` of `librosa.cqt`.
+    const SPARSITY: f32 = 0.01;
+
+    /// Constant-Q transform, `librosa.cqt(y, sr, hop_length, fmin, n_bins, bins_per_octave, tuning)`
+    /// with Hann windowed, L1 normalized filters scaled by `1 / sqrt(length)`.
+    ///
+    /// librosa runs each octave at a halved sample rate. Here every filter runs at the full rate
+    /// in one pass, which gives the same response up to librosa's resampling error.
+    pub struct ConstantQ {
+        stft: Stft,
+        /// Non-negligible `(bin, value)` pairs of each filter's spectrum.
+        basis: Vec<Vec<(usize, Complex<f32>)>>,
+        /// Square root of each filter's length.
+        scale: Vec<f32>,
+    }
+
+    impl ConstantQ {
+        pub fn new(
+            planner: &mut RealFftPlanner<f32>,
+            sr: f64,
+            hop: usize,
+            fmin: f64,
+            n_bins: usize,
+            bins_per_octave: usize,
+            tuning: f64,
+        ) -> Self {
+            let fmin = fmin * 2f64.powf(tuning / bins_per_octave as f64);
+            let frequencies: Vec<f64> = (0..n_bins)
+                .map(|k| fmin * 2f64.powf(k as f64 / bins_per_octave as f64))
+                .collect();
+
+            // Q of equally spaced bins with filter_scale = 1
+            let r = 2f64.powf(2.0 / bins_per_octave as f64);
+            let q = (r + 1.0) / (r - 1.0);
+            let lengths: Vec<f64> = frequencies.iter().map(|f| q * sr / f).collect();
+
+            let longest = lengths.iter().cloned().fold(0.0, f64::max);
+            let n_fft = (2f64.powf(longest.log2().ceil()) as usize).max(2 * hop.next_power_of_two());
+
+            let fft = planner.plan_fft_forward(n_fft);
+            let (mut real, mut imaginary) = (fft.make_input_vec(), fft.make_input_vec());
+            let (mut real_spectrum, mut imaginary_spectrum) = (fft.make_output_vec(), fft.make_output_vec());
+
+            let basis = frequencies
+                .iter()
+                .zip(&lengths)
+                .map(|(frequency, length)| {
+                    // np.arange(-length // 2, length // 2), centred in n_fft
+                    let start = (-length / 2.0).floor();
+                    let count = ((length / 2.0).floor() - start) as usize;
+                    let offset = (n_fft - count) / 2;
+
+                    let window = hann(count);
+                    let l1: f64 = window.iter().sum();
+
+                    real.fill(0.0);
+                    imaginary.fill(0.0);
+                    for (m, w) in window.iter().enumerate() {
+                        let phase = 2.0 * PI * frequency / sr * (start + m as f64);
+                        let amplitude = w / l1 * length / n_fft as f64;
+
+                        real[offset + m] = (amplitude * phase.cos()) as f32;
+                        imaginary[offset + m] = (amplitude * phase.sin()) as f32;
+                    }
+
+                    // FFT of the complex filter from the FFTs of its two real parts
+                    fft.process(&mut real, &mut real_spectrum).expect("FFT buffers come from the plan");
+                    fft.process(&mut imaginary, &mut imaginary_spectrum)
+                        .expect("FFT buffers come from the plan");
+
+                    let spectrum: Vec<Complex<f32>> = real_spectrum
+                        .iter()
+                        .zip(&imaginary_spectrum)
+                        .map(|(re, im)| Complex::new(re.re - im.im, re.im + im.re))
+                        .collect();
+
+                    sparsify(&spectrum)
+                })
+                .collect();
+
+            Self {
+                stft: Stft::rectangular(planner, n_fft, hop),
+                basis,
+                scale: lengths.iter().map(|length| length.sqrt() as f32).collect(),
+            }
+        }
+
+        /// `(n_bins, 1 + len / hop)` magnitudes.
+        pub fn magnitude(&self, signal: &[f32]) -> Array2<f32> {
+            let mut response = Array2::zeros((self.basis.len(), self.stft.n_frames(signal.len())));
+
+            self.stft.for_each_frame(signal, |t, bins| {
+                for (k, filter) in self.basis.iter().enumerate() {
+                    let value: Complex<f32> = filter.iter().map(|(bin, weight)| *weight * bins[*bin]).sum();
+                    response[[k, t]] = value.norm() / self.scale[k];
+                }
+            });
+
+            response
+        }
+    }
+
+    /// `librosa.util.sparsify_rows` for one row: drops the smallest values that together hold
+    /// `SPARSITY` of the total magnitude.
+    fn sparsify(spectrum: &[Complex<f32>]) -> Vec<(usize, Complex<f32>)> {
+        let magnitudes: Vec<f32> = spectrum.iter().map(|v| v.norm()).collect();
+        let total: f32 = magnitudes.iter().sum();
+
+        let mut sorted = magnitudes.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let mut cumulative = 0.0;
+        let threshold = sorted
+            .iter()
+            .find(|m| {
+                cumulative += *m / total;
+                cumulative >= SPARSITY
+            })
+            .copied()
+            .unwrap_or(0.0);
+
+        magnitudes
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| **m >= threshold)
+            .map(|(i, _)| (i, spectrum[i]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f64::consts::PI;
+
+    use ndarray::s;
+    use ndarray_npy::read_npy;
+
+    use super::*;
+    use crate::features::chroma::{pitch_tuning, piptrack};
+    use crate::features::spectral::{hz_to_mel, mel_to_hz};
+    use crate::ml::manifest::ModelManifest;
+
+    fn sine(frequency: f64, samples: usize) -> Vec<f32> {
+        (0..samples)
+            .map(|i| (2.0 * PI * frequency * i as f64 / SAMPLE_RATE as f64).sin() as f32)
+            .collect()
+    }
+
+    fn argmax(values: impl Iterator<Item = f32>) -> usize {
+        values
+            .enumerate()
+            .fold((0, f32::NEG_INFINITY), |best, (i, v)| if v > best.1 { (i, v) } else { best })
+            .0
+    }
+
+    #[test]
+    fn middle_sample_is_faded_normalized_and_framed_like_the_etl() {
+        let signal = vec![0.5f32; 31 * SAMPLE_RATE as usize];
+
+        let sample = middle_sample(&signal).unwrap();
+        let frames = split_to_frames(&sample);
+
+        assert_eq!(sample.len(), SAMPLE_SECONDS * SAMPLE_RATE as usize);
+        assert_eq!(sample[0], 0.0);
+        assert_eq!(sample[sample.len() / 2], 1.0);
+        assert_eq!(frames.dim(), (291, FRAME_LENGTH as usize));
+        assert_eq!(frames[[1, 0]], sample[FRAME_HOP as usize]);
+    }
+
+    #[test]
+    fn thirty_seconds_or_less_is_too_short() {
+        let signal = vec![0.5f32; 30 * SAMPLE_RATE as usize];
+
+        assert!(matches!(middle_sample(&signal), Err(FeatureError::TooShort(_))));
+    }
+
+    #[test]
+    fn mel_scale_and_dct_match_librosa_conventions() {
+        assert!((hz_to_mel(1000.0) - 15.0).abs() < 1e-12);
+        assert!((mel_to_hz(hz_to_mel(4321.0)) - 4321.0).abs() < 1e-9);
+
+        let dct = dct_matrix(8, 8);
+        let identity = dct.dot(&dct.t());
+        for ((i, j), v) in identity.indexed_iter() {
+            assert!((v - if i == j { 1.0 } else { 0.0 }).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn tuning_is_read_from_the_strongest_peaks() {
+        let detuned = 440.0 * 2f64.powf(0.3 / 12.0);
+
+        assert_eq!(pitch_tuning(&[440.0, 440.0, 220.0], 12), 0.0);
+        assert!((pitch_tuning(&[detuned as f32], 12) - 0.3).abs() < 0.011);
+
+        let mut planner = RealFftPlanner::new();
+        let power = Stft::hann(&mut planner, N_FFT, HOP).power(&sine(detuned, FRAME_LENGTH as usize));
+
+        assert!(!piptrack(&power, SAMPLE_RATE as f64, N_FFT).is_empty());
+        // parabolic interpolation of a Hann windowed peak is biased by a few hundredths
+        assert!((estimate_tuning(&power, SAMPLE_RATE as f64, N_FFT, N_CHROMA) - 0.3).abs() < 0.05);
+    }
+
+    #[test]
+    fn chroma_of_an_a_peaks_at_a() {
+        let mut extractor = FeatureExtractor::new();
+
+        let chroma = extractor.stft_frame(&Feature::ChromaStft, &sine(440.0, FRAME_LENGTH as usize));
+
+        assert_eq!(chroma.dim(), (12, 87));
+        assert_eq!(argmax(chroma.column(40).iter().copied()), 9);
+    }
+
+    #[test]
+    fn constant_q_peaks_at_the_bin_of_the_tone() {
+        let mut planner = RealFftPlanner::new();
+        let constant_q = ConstantQ::new(&mut planner, SAMPLE_RATE as f64, CQT_HOP, 220.0, 72, 36, 0.0);
+
+        let cqt = constant_q.magnitude(&sine(440.0, FRAME_LENGTH as usize));
+
+        assert_eq!(cqt.dim(), (72, 44));
+        assert_eq!(argmax(cqt.column(22).iter().copied()), 36);
+    }
+
+    #[test]
+    fn cens_and_tonnetz_keep_silent_frames_at_zero() {
+        let mut chroma = Array2::zeros((12, 3));
+        chroma[[0, 1]] = 2.0;
+        chroma[[7, 1]] = 1.0;
+
+        let cens = cens(&chroma);
+        let tonnetz = tonnetz(&chroma);
+
+        assert_eq!(tonnetz.dim(), (6, 3));
+        assert!(tonnetz.column(0).iter().all(|v| *v == 0.0));
+        assert!(tonnetz.column(1).iter().any(|v| *v != 0.0));
+        // smoothing spreads the middle frame to its neighbours, each L2 normalized
+        for column in cens.columns() {
+            assert!((column.iter().map(|v| v * v).sum::<f32>() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn stft_features_have_the_manifest_input_shapes() {
+        let manifest = ModelManifest::parse(include_str!("../util/models.json")).unwrap();
+        let stats = NormalizationStats::new("../data/artifacts");
+        let mut extractor = FeatureExtractor::new();
+
+        let signal: Vec<f32> = sine(220.0, 2 * FRAME_LENGTH as usize)
+            .iter()
+            .zip(sine(331.0, 2 * FRAME_LENGTH as usize))
+            .map(|(a, b)| a * 0.6 + b * 0.3)
+            .collect();
+        let frames = split_to_frames(&signal);
+
+        for feature in [
+            Feature::Ft,
+            Feature::Spectrogram,
+            Feature::MelSpectrogram,
+            Feature::PowerSpectrogram,
+            Feature::Mfcc,
+            Feature::ChromaStft,
+        ] {
+            let values = extractor.transform(&feature, &frames, &stats).unwrap();
+            let input_shape = &manifest.entry(&feature).input_shape;
+
+            assert_eq!(values.shape()[0], frames.nrows());
+            assert_eq!(
+                [values.shape()[1] as i64, values.shape()[2] as i64],
+                [input_shape[0], input_shape[1]],
+                "{:?}",
+                feature
+            );
+            assert!(values.iter().all(|v| v.is_finite()), "{:?}", feature);
+        }
+    }
+
+    /// Needs `util/fixtures/features`, written from `data/` by
+    /// `python -m server_utils.fixtures_gen ../back/util/fixtures/features`.
+    #[test]
+    #[ignore = "needs the fixtures written by data/server_utils/fixtures_gen.py"]
+    fn features_match_etl_fixtures() {
+        let fixtures = Path::new("util/fixtures/features");
+        let stats = NormalizationStats::new("../data/artifacts");
+        let mut extractor = FeatureExtractor::new();
+
+        let signal: Array1<f32> = read_npy(fixtures.join("signal.npy")).expect("Fixtures should be generated");
+        let expected_frames: Array2<f32> = read_npy(fixtures.join("frames.npy")).unwrap();
+
+        let frames = split_to_frames(&middle_sample(&signal.to_vec()).unwrap());
+        let frames = frames.slice(s![..expected_frames.nrows(), ..]).to_owned();
+        let frame_difference = (&frames - &expected_frames).fold(0.0f32, |a, b| a.max(b.abs()));
+        assert!(frame_difference < 1e-6, "frames differ by {}", frame_difference);
+
+        // the CQT is computed at the full rate rather than librosa's octave by octave resampling
+        for (feature, tolerance) in [
+            (Feature::Ft, 1e-3),
+            (Feature::Spectrogram, 1e-2),
+            (Feature::MelSpectrogram, 1e-2),
+            (Feature::PowerSpectrogram, 1e-2),
+            (Feature::Mfcc, 1e-2),
+            (Feature::ChromaStft, 1e-2),
+            (Feature::ChromaCqt, 5e-2),
+            (Feature::ChromaCens, 5e-2),
+            (Feature::Tonnetz, 5e-2),
+        ] {
+            let expected: Array3<f32> = read_npy(find_signal_path_in(fixtures, &feature)).unwrap();
+            let actual = extractor.transform(&feature, &frames, &stats).unwrap();
+
+            assert_eq!(actual.shape(), expected.shape(), "{:?}", feature);
+
+            let difference = (&actual - &expected).fold(0.0f32, |a, b| a.max(b.abs()));
+            assert!(
+                difference < tolerance,
+                "{:?} differs by up to {:e}, allowed {:e}",
+                feature,
+                difference,
+                tolerance
+            );
+        }
+    }
+}
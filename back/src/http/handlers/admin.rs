@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    http::{
+        handlers::api::{api_error, API_SCHEMA_VERSION},
+        AppState,
+    },
+    ml::ml::ModelRegistry,
+};
+
+/// Header carrying the value of `ADMIN_TOKEN`.
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+#[derive(Serialize)]
+pub struct ModelsReloaded {
+    pub schema_version: u32,
+    pub manifest_version: String,
+    pub available_features: Vec<&'static str>,
+    pub unavailable_features: BTreeMap<&'static str, String>,
+}
+
+impl ModelsReloaded {
+    fn new(registry: &ModelRegistry) -> Self {
+        Self {
+            schema_version: API_SCHEMA_VERSION,
+            manifest_version: registry.manifest().version.clone(),
+            available_features: registry.available_features().iter().map(|f| f.key()).collect(),
+            unavailable_features: registry
+                .unavailable()
+                .iter()
+                .map(|(feature, reason)| (feature.key(), reason.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Admin endpoints are disabled unless `ADMIN_TOKEN` is set.
+fn authorize(headers: &HeaderMap) -> Result<(), Response> {
+    let Ok(token) = std::env::var("ADMIN_TOKEN") else {
+        return Err(api_error(StatusCode::FORBIDDEN, "Admin endpoints are disabled".to_string()));
+    };
+
+    match headers.get(ADMIN_TOKEN_HEADER).and_then(|v| v.to_str().ok()) {
+        Some(given) if !token.is_empty() && tokens_match(given, &token) => Ok(()),
+        _ => Err(api_error(StatusCode::UNAUTHORIZED, "Invalid admin token".to_string())),
+    }
+}
+
+/// Compares the SHA-256 digests of both tokens without stopping at the first differing byte,
+/// so the time taken doesn't reveal how much of the token was guessed, nor its length.
+fn tokens_match(given: &str, token: &str) -> bool {
+    let given = Sha256::digest(given.as_bytes());
+    let token = Sha256::digest(token.as_bytes());
+
+    given.iter().zip(token.iter()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Loads the models again in the background; requests keep using the old ones until the swap.
+pub async fn reload_models(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Err(response) = authorize(&headers) {
+        return response;
+    }
+
+    match state.inference.reload().await {
+        Ok(registry) => Json(ModelsReloaded::new(&registry)).into_response(),
+        Err(e) => {
+            tracing::error!("Model reload failed: {}", e);
+            api_error(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+    }
+}
@@ -0,0 +1,417 @@
+use axum::{
+    extract::{rejection::QueryRejection, Path, Query, State},
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{
+        db_conn::{get_upload, Upload},
+        segments::get_segment_analysis,
+        similarity::{get_track_vector_space, save_track_vector, similar_tracks, SimilarTrack},
+    },
+    http::{
+        handlers::{
+            compare::{load_tracks, CompareError, CompareQuery},
+            track_menu::{inference_error_status, stored_or_classify, BUSY_RETRY_AFTER},
+        },
+        AppState,
+    },
+    ml::{
+        ablation::AblationReport,
+        ensemble::Ensemble,
+        inference::InferenceError,
+        ml::{ModelDescription, SongClassificationResult, TimelineFrame, FRAME_HOP, SAMPLE_RATE},
+        segments::SegmentAnalysis,
+        similarity::{SimilarityScope, TrackVector},
+    },
+};
+
+/// Bumped whenever a field of the JSON responses is renamed, removed or changes meaning.
+pub const API_SCHEMA_VERSION: u32 = 1;
+
+/// Similar tracks returned when `?k=` isn't given, and the most that can be asked for.
+pub const DEFAULT_SIMILAR_TRACKS: i64 = 5;
+pub const MAX_SIMILAR_TRACKS: i64 = 50;
+
+#[derive(Serialize)]
+pub struct ClassificationResponse {
+    pub schema_version: u32,
+    pub upload_uuid: String,
+    #[serde(flatten)]
+    pub classification: SongClassificationResult,
+}
+
+#[derive(Serialize)]
+pub struct TimelineResponse {
+    pub schema_version: u32,
+    pub upload_uuid: String,
+    pub strategy: Ensemble,
+    pub class_labels: Vec<String>,
+    pub hop_seconds: f32,
+    pub frames: Vec<TimelineFrame>,
+}
+
+#[derive(Serialize)]
+pub struct CompareResponse {
+    pub schema_version: u32,
+    pub strategy: Ensemble,
+    pub class_labels: Vec<String>,
+    pub hop_seconds: f32,
+    pub tracks: Vec<ComparedTrackResponse>,
+}
+
+#[derive(Serialize)]
+pub struct ComparedTrackResponse {
+    pub upload_uuid: String,
+    pub file_name: String,
+    #[serde(flatten)]
+    pub classification: SongClassificationResult,
+    pub timeline: Vec<TimelineFrame>,
+}
+
+#[derive(Serialize)]
+pub struct SegmentsResponse {
+    pub schema_version: u32,
+    pub upload_uuid: String,
+    #[serde(flatten)]
+    pub analysis: SegmentAnalysis,
+}
+
+#[derive(Serialize)]
+pub struct AblationResponse {
+    pub schema_version: u32,
+    pub upload_uuid: String,
+    #[serde(flatten)]
+    pub ablation: AblationReport,
+}
+
+#[derive(Serialize)]
+pub struct SimilarResponse {
+    pub schema_version: u32,
+    pub upload_uuid: String,
+    /// What the vectors are made of, only tracks of the same space are compared.
+    pub space: String,
+    pub scope: SimilarityScope,
+    pub tracks: Vec<SimilarTrack>,
+}
+
+#[derive(Serialize)]
+pub struct ModelsResponse {
+    pub schema_version: u32,
+    pub manifest_version: String,
+    pub class_labels: Vec<String>,
+    /// Strategy used when a request doesn't ask for one.
+    pub strategy: Ensemble,
+    pub models: Vec<ModelDescription>,
+}
+
+#[derive(Serialize)]
+pub struct ApiError {
+    pub schema_version: u32,
+    pub error: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ClassificationQuery {
+    #[serde(default)]
+    pub per_frame: bool,
+    pub strategy: Option<Ensemble>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TimelineQuery {
+    pub strategy: Option<Ensemble>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AblationQuery {
+    pub strategy: Option<Ensemble>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SimilarQuery {
+    pub k: Option<i64>,
+    #[serde(default)]
+    pub scope: SimilarityScope,
+}
+
+/// Malformed query parameters, e.g. an unknown `strategy`, answer with an `ApiError` too.
+fn query_error(rejection: QueryRejection) -> Response {
+    api_error(rejection.status(), rejection.body_text())
+}
+
+pub fn api_error(status: StatusCode, error: String) -> Response {
+    (
+        status,
+        Json(ApiError {
+            schema_version: API_SCHEMA_VERSION,
+            error,
+        }),
+    )
+        .into_response()
+}
+
+/// Resolves the upload and returns its stored (or freshly computed) classification.
+async fn upload_classification(
+    state: &AppState,
+    upload_uuid: &str,
+) -> Result<(String, SongClassificationResult), Response> {
+    let upload = find_upload(upload_uuid).await?;
+    let classification = classification_of(state, &upload).await?;
+
+    Ok((upload.upload_uuid, classification))
+}
+
+async fn find_upload(upload_uuid: &str) -> Result<Upload, Response> {
+    get_upload(upload_uuid.to_string()).await.map_err(|e| {
+        tracing::info!("{:?}", e);
+        api_error(StatusCode::NOT_FOUND, format!("Upload {} not found", upload_uuid))
+    })
+}
+
+async fn classification_of(state: &AppState, upload: &Upload) -> Result<SongClassificationResult, Response> {
+    stored_or_classify(state, upload).await.map_err(|e| {
+        tracing::warn!("{}", e);
+        let mut response = api_error(inference_error_status(&e), e.to_string());
+        if let InferenceError::Busy = e {
+            response.headers_mut().insert(RETRY_AFTER, HeaderValue::from_static(BUSY_RETRY_AFTER));
+        }
+        response
+    })
+}
+
+pub async fn track_classification(
+    State(state): State<AppState>,
+    Path(upload_uuid): Path<String>,
+    query: Result<Query<ClassificationQuery>, QueryRejection>,
+) -> Response {
+    let Query(query) = match query {
+        Ok(query) => query,
+        Err(rejection) => return query_error(rejection),
+    };
+    let (upload_uuid, classification) = match upload_classification(&state, &upload_uuid).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let mut classification = classification.with_strategy(query.strategy.unwrap_or(state.ensemble));
+
+    if !query.per_frame {
+        classification
+            .feature_classification_result
+            .iter_mut()
+            .for_each(|f| f.per_frame_classifications.clear());
+    }
+
+    Json(ClassificationResponse {
+        schema_version: API_SCHEMA_VERSION,
+        upload_uuid,
+        classification,
+    })
+    .into_response()
+}
+
+pub async fn track_timeline(
+    State(state): State<AppState>,
+    Path(upload_uuid): Path<String>,
+    query: Result<Query<TimelineQuery>, QueryRejection>,
+) -> Response {
+    let Query(query) = match query {
+        Ok(query) => query,
+        Err(rejection) => return query_error(rejection),
+    };
+    let (upload_uuid, classification) = match upload_classification(&state, &upload_uuid).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let classification = classification.with_strategy(query.strategy.unwrap_or(state.ensemble));
+
+    Json(TimelineResponse {
+        schema_version: API_SCHEMA_VERSION,
+        upload_uuid,
+        strategy: classification.strategy,
+        hop_seconds: FRAME_HOP as f32 / SAMPLE_RATE as f32,
+        frames: classification.get_timeline(),
+        class_labels: classification.class_labels,
+    })
+    .into_response()
+}
+
+/// Classifications and timelines of several tracks, in the order of `tracks`.
+pub async fn compare_classifications(
+    State(state): State<AppState>,
+    query: Result<Query<CompareQuery>, QueryRejection>,
+) -> Response {
+    let Query(query) = match query {
+        Ok(query) => query,
+        Err(rejection) => return query_error(rejection),
+    };
+    let strategy = query.strategy.unwrap_or(state.ensemble);
+
+    let tracks = match load_tracks(&state, &query.tracks, strategy).await {
+        Ok(tracks) => tracks,
+        Err(e) => {
+            tracing::info!("{}", e);
+            let mut response = api_error(e.status(), e.to_string());
+            if let CompareError::Inference(_, InferenceError::Busy) = e {
+                response.headers_mut().insert(RETRY_AFTER, HeaderValue::from_static(BUSY_RETRY_AFTER));
+            }
+            return response;
+        }
+    };
+
+    let class_labels = tracks[0].classification.class_labels.clone();
+
+    let tracks = tracks
+        .into_iter()
+        .map(|track| {
+            let timeline = track.classification.get_timeline();
+            let mut classification = track.classification;
+            classification
+                .feature_classification_result
+                .iter_mut()
+                .for_each(|f| f.per_frame_classifications.clear());
+
+            ComparedTrackResponse {
+                upload_uuid: track.upload_uuid,
+                file_name: track.file_name,
+                classification,
+                timeline,
+            }
+        })
+        .collect();
+
+    Json(CompareResponse {
+        schema_version: API_SCHEMA_VERSION,
+        strategy,
+        class_labels,
+        hop_seconds: FRAME_HOP as f32 / SAMPLE_RATE as f32,
+        tracks,
+    })
+    .into_response()
+}
+
+/// Stored sliding-window analysis, started with `POST /track/{upload_name}/segments`.
+pub async fn track_segments(Path(upload_uuid): Path<String>) -> Response {
+    match get_segment_analysis(&upload_uuid).await {
+        Ok(Some(analysis)) => Json(SegmentsResponse {
+            schema_version: API_SCHEMA_VERSION,
+            upload_uuid,
+            analysis,
+        })
+        .into_response(),
+        Ok(None) => api_error(StatusCode::NOT_FOUND, format!("Upload {} wasn't analysed by segments", upload_uuid)),
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Segment analysis couldn't be read".to_string())
+        }
+    }
+}
+
+pub async fn track_ablation(
+    State(state): State<AppState>,
+    Path(upload_uuid): Path<String>,
+    query: Result<Query<AblationQuery>, QueryRejection>,
+) -> Response {
+    let Query(query) = match query {
+        Ok(query) => query,
+        Err(rejection) => return query_error(rejection),
+    };
+    let (upload_uuid, classification) = match upload_classification(&state, &upload_uuid).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let classification = classification.with_strategy(query.strategy.unwrap_or(state.ensemble));
+
+    Json(AblationResponse {
+        schema_version: API_SCHEMA_VERSION,
+        upload_uuid,
+        ablation: AblationReport::new(&classification),
+    })
+    .into_response()
+}
+
+/// Only for the track's owner, the results carry the file names of their uploads.
+pub async fn track_similar(
+    State(state): State<AppState>,
+    Path(upload_uuid): Path<String>,
+    query: Result<Query<SimilarQuery>, QueryRejection>,
+    jar: CookieJar,
+) -> Response {
+    let Query(query) = match query {
+        Ok(query) => query,
+        Err(rejection) => return query_error(rejection),
+    };
+    let Some(user_uuid) = jar.get("uuid").map(|cookie| cookie.value().to_string()) else {
+        return api_error(StatusCode::UNAUTHORIZED, "Similar tracks need a logged in user".to_string());
+    };
+
+    let upload = match find_upload(&upload_uuid).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+    if upload.user_uuid != user_uuid {
+        return api_error(StatusCode::FORBIDDEN, format!("Upload {} isn't yours", upload.upload_uuid));
+    }
+
+    let classification = match classification_of(&state, &upload).await {
+        Ok(classification) => classification,
+        Err(response) => return response,
+    };
+    let upload_uuid = upload.upload_uuid;
+
+    let space = match get_track_vector_space(&upload_uuid).await {
+        Ok(Some(space)) => space,
+        // classified before vectors were stored, the stored result only has probabilities
+        Ok(None) => {
+            let vector = TrackVector::from_result(&classification);
+            if let Err(e) = save_track_vector(&upload_uuid, &vector).await {
+                tracing::error!("{:?}", e);
+                return api_error(StatusCode::INTERNAL_SERVER_ERROR, "Track vector couldn't be stored".to_string());
+            }
+            vector.space
+        }
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            return api_error(StatusCode::INTERNAL_SERVER_ERROR, "Track vector couldn't be read".to_string());
+        }
+    };
+
+    let k = query.k.unwrap_or(DEFAULT_SIMILAR_TRACKS).clamp(1, MAX_SIMILAR_TRACKS);
+
+    let tracks = match similar_tracks(&upload_uuid, &user_uuid, k, query.scope).await {
+        Ok(tracks) => tracks,
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            return api_error(StatusCode::INTERNAL_SERVER_ERROR, "Similar tracks couldn't be queried".to_string());
+        }
+    };
+
+    Json(SimilarResponse {
+        schema_version: API_SCHEMA_VERSION,
+        upload_uuid,
+        space,
+        scope: query.scope,
+        tracks,
+    })
+    .into_response()
+}
+
+pub async fn models_info(State(state): State<AppState>) -> Response {
+    let models = state.inference.models();
+
+    Json(ModelsResponse {
+        schema_version: API_SCHEMA_VERSION,
+        manifest_version: models.manifest().version.clone(),
+        class_labels: models.manifest().class_labels.clone(),
+        strategy: state.ensemble,
+        models: models.describe(),
+    })
+    .into_response()
+}
\ No newline at end of file
@@ -0,0 +1,300 @@
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Response},
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{db_conn::get_upload, results::upload_uuid_from_name},
+    http::{
+        handlers::{
+            track_menu::{inference_error_response, inference_error_status, stored_or_classify},
+            HtmlTemplate,
+        },
+        AppState,
+    },
+    ml::{
+        ensemble::Ensemble,
+        inference::InferenceError,
+        ml::{Feature, SongClassificationResult, TimelineFrame},
+    },
+};
+
+/// Most tracks a single comparison classifies and renders.
+pub const MAX_COMPARED_TRACKS: usize = 6;
+
+#[derive(Deserialize, Debug)]
+pub struct CompareQuery {
+    /// Comma separated upload uuids or `<upload_uuid>-<file_name>` names.
+    #[serde(default)]
+    pub tracks: String,
+    pub strategy: Option<Ensemble>,
+}
+
+pub struct ComparedTrack {
+    pub upload_uuid: String,
+    pub file_name: String,
+    pub upload_name: String,
+    pub classification: SongClassificationResult,
+}
+
+/// Weighted results of one feature for every compared track, `None` where the feature is missing.
+pub struct FeatureComparison {
+    pub feature: Feature,
+    pub tracks: Vec<Option<Vec<String>>>,
+}
+
+/// Drawn by the page's script, embedded so the tracks aren't classified a second time through `/api/compare`.
+#[derive(Serialize)]
+pub struct CompareTimelines {
+    pub class_labels: Vec<String>,
+    pub tracks: Vec<TrackTimeline>,
+}
+
+#[derive(Serialize)]
+pub struct TrackTimeline {
+    pub upload_uuid: String,
+    pub timeline: Vec<TimelineFrame>,
+}
+
+#[derive(Template)]
+#[template(path = "compare.html")]
+pub struct CompareTemplate {
+    pub query: String,
+    pub strategy: Ensemble,
+    pub strategies: Vec<Ensemble>,
+    pub class_labels: Vec<String>,
+    pub tracks: Vec<ComparedTrack>,
+    pub cum_class: Vec<Vec<String>>,
+    pub features: Vec<FeatureComparison>,
+    /// `CompareTimelines` as JSON.
+    pub timelines: String,
+}
+
+#[derive(Debug)]
+pub enum CompareError {
+    /// Fewer than two or more than `MAX_COMPARED_TRACKS` distinct tracks, or a malformed one.
+    InvalidQuery(String),
+    NotFound(String),
+    /// Tracks classified with different `class_labels` can't share a table.
+    LabelMismatch(String),
+    Inference(String, InferenceError),
+}
+
+impl CompareError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            CompareError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            CompareError::NotFound(_) => StatusCode::NOT_FOUND,
+            CompareError::LabelMismatch(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            CompareError::Inference(_, e) => inference_error_status(e),
+        }
+    }
+}
+
+impl std::fmt::Display for CompareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompareError::InvalidQuery(e) => write!(f, "{}", e),
+            CompareError::NotFound(track) => write!(f, "Upload {} not found", track),
+            CompareError::LabelMismatch(track) => write!(
+                f,
+                "{} was classified with different genres than the first track, reclassify it to compare them",
+                track
+            ),
+            CompareError::Inference(track, e) => write!(f, "{}: {}", track, e),
+        }
+    }
+}
+
+/// Upload uuids of the `tracks` parameter, in order and without duplicates.
+pub fn parse_tracks(tracks: &str) -> Result<Vec<String>, CompareError> {
+    let mut uuids: Vec<String> = Vec::new();
+
+    for track in tracks.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        let uuid = upload_uuid_from_name(track)
+            .ok_or(CompareError::InvalidQuery(format!("{} is not an upload uuid", track)))?;
+
+        if !uuids.iter().any(|u| u == uuid) {
+            uuids.push(uuid.to_string());
+        }
+    }
+
+    if uuids.len() < 2 || uuids.len() > MAX_COMPARED_TRACKS {
+        return Err(CompareError::InvalidQuery(format!(
+            "Between 2 and {} different tracks can be compared, got {}",
+            MAX_COMPARED_TRACKS,
+            uuids.len()
+        )));
+    }
+
+    Ok(uuids)
+}
+
+/// Stored (or freshly computed) results of every track, recombined with `strategy`.
+pub async fn load_tracks(
+    state: &AppState,
+    tracks: &str,
+    strategy: Ensemble,
+) -> Result<Vec<ComparedTrack>, CompareError> {
+    let mut compared: Vec<ComparedTrack> = Vec::new();
+
+    for upload_uuid in parse_tracks(tracks)? {
+        let upload = get_upload(upload_uuid.clone()).await.map_err(|e| {
+            tracing::info!("{:?}", e);
+            CompareError::NotFound(upload_uuid.clone())
+        })?;
+
+        let upload_name = upload.upload_name();
+
+        let classification = stored_or_classify(state, &upload)
+            .await
+            .map_err(|e| CompareError::Inference(upload_name.clone(), e))?
+            .with_strategy(strategy);
+
+        if let Some(first) = compared.first() {
+            if first.classification.class_labels != classification.class_labels {
+                return Err(CompareError::LabelMismatch(upload_name));
+            }
+        }
+
+        compared.push(ComparedTrack {
+            upload_uuid: upload.upload_uuid,
+            file_name: upload.file_name,
+            upload_name,
+            classification,
+        });
+    }
+
+    Ok(compared)
+}
+
+pub async fn compare_tracks(
+    State(state): State<AppState>,
+    Query(query): Query<CompareQuery>,
+) -> Response {
+    let strategy = query.strategy.unwrap_or(state.ensemble);
+
+    let tracks = match load_tracks(&state, &query.tracks, strategy).await {
+        Ok(tracks) => tracks,
+        Err(CompareError::Inference(upload_name, e)) => return inference_error_response(e, upload_name),
+        Err(e) => {
+            tracing::info!("{}", e);
+            return (e.status(), e.to_string()).into_response();
+        }
+    };
+
+    let cum_class = tracks
+        .iter()
+        .map(|t| t.classification.cum_classification.iter().map(|x| format!("{:.2}%", x * 100.0)).collect())
+        .collect();
+
+    let features = Feature::all()
+        .into_iter()
+        .map(|feature| FeatureComparison {
+            tracks: tracks
+                .iter()
+                .map(|t| {
+                    t.classification
+                        .feature_classification_result
+                        .iter()
+                        .find(|c| c.feature == feature)
+                        .map(|c| c.weighted_avg_classification_string.clone())
+                })
+                .collect(),
+            feature,
+        })
+        .filter(|f| f.tracks.iter().any(Option::is_some))
+        .collect();
+
+    let timelines = CompareTimelines {
+        class_labels: tracks[0].classification.class_labels.clone(),
+        tracks: tracks
+            .iter()
+            .map(|t| TrackTimeline {
+                upload_uuid: t.upload_uuid.clone(),
+                timeline: t.classification.get_timeline(),
+            })
+            .collect(),
+    };
+    // `<` escaped so nothing in it can close the script element
+    let timelines = serde_json::to_string(&timelines)
+        .unwrap_or_else(|_| "null".to_string())
+        .replace('<', "\\u003c");
+
+    let template = CompareTemplate {
+        query: query.tracks,
+        strategy,
+        strategies: Ensemble::all().to_vec(),
+        class_labels: tracks[0].classification.class_labels.clone(),
+        cum_class,
+        features,
+        tracks,
+        timelines,
+    };
+
+    HtmlTemplate(template).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const FIRST: &str = "8d298e5b-e11a-4ab4-ab38-7149c710a90a";
+    const SECOND: &str = "0f8fad5b-d9cb-469f-a165-70867728950e";
+
+    fn uuid(idx: usize) -> String {
+        format!("00000000-0000-4000-8000-{:012}", idx)
+    }
+
+    #[test]
+    fn tracks_are_parsed_from_uuids_and_upload_names() {
+        let tracks = parse_tracks(&format!(" {}-track.mp3 , {},", FIRST, SECOND)).unwrap();
+        assert_eq!(tracks, vec![FIRST.to_string(), SECOND.to_string()]);
+    }
+
+    #[test]
+    fn duplicate_tracks_are_compared_once() {
+        let tracks = parse_tracks(&format!("{},{}-track.mp3,{}", FIRST, FIRST, SECOND)).unwrap();
+        assert_eq!(tracks, vec![FIRST.to_string(), SECOND.to_string()]);
+
+        // only one distinct track is left
+        let Err(CompareError::InvalidQuery(e)) = parse_tracks(&format!("{},{}", FIRST, FIRST)) else {
+            panic!("A single track shouldn't be compared");
+        };
+        assert!(e.ends_with("got 1"), "{}", e);
+    }
+
+    #[test]
+    fn too_many_tracks_are_rejected() {
+        let tracks: Vec<String> = (0..MAX_COMPARED_TRACKS).map(uuid).collect();
+        assert_eq!(parse_tracks(&tracks.join(",")).unwrap().len(), MAX_COMPARED_TRACKS);
+
+        let tracks: Vec<String> = (0..=MAX_COMPARED_TRACKS).map(uuid).collect();
+        let Err(CompareError::InvalidQuery(e)) = parse_tracks(&tracks.join(",")) else {
+            panic!("More than {} tracks shouldn't be compared", MAX_COMPARED_TRACKS);
+        };
+        assert!(e.ends_with(&format!("got {}", MAX_COMPARED_TRACKS + 1)), "{}", e);
+    }
+
+    #[test]
+    fn invalid_uuids_are_rejected() {
+        let Err(CompareError::InvalidQuery(e)) = parse_tracks(&format!("{},not-a-track", FIRST)) else {
+            panic!("An invalid uuid shouldn't be accepted");
+        };
+        assert_eq!(e, "not-a-track is not an upload uuid");
+    }
+
+    #[test]
+    fn empty_track_list_is_rejected() {
+        for tracks in ["", " , ,"] {
+            let Err(CompareError::InvalidQuery(e)) = parse_tracks(tracks) else {
+                panic!("{:?} shouldn't be accepted", tracks);
+            };
+            assert!(e.ends_with("got 0"), "{}", e);
+        }
+    }
+}
@@ -2,9 +2,14 @@ use askama::Template;
 use axum::response::{Html, IntoResponse};
 use axum::http::StatusCode;
 
+pub mod admin;
+pub mod api;
+pub mod compare;
 pub mod delete;
+pub mod models;
 pub mod profile;
 pub mod register;
+pub mod segments;
 pub mod upload;
 pub mod track_menu;
 
@@ -0,0 +1,34 @@
+use askama::Template;
+use axum::{extract::State, response::IntoResponse};
+
+use crate::{
+    http::{handlers::HtmlTemplate, AppState},
+    ml::{ensemble::Ensemble, ml::ModelDescription},
+};
+
+#[derive(Template)]
+#[template(path = "models.html")]
+pub struct ModelsPage {
+    pub manifest_version: String,
+    pub class_labels: Vec<String>,
+    pub strategy: Ensemble,
+    pub models: Vec<ModelDescription>,
+    /// The manifest's description, models only list theirs when it differs.
+    pub training_data: Option<String>,
+}
+
+/// The model behind every feature: its file, parameters, ensemble weight, validation metrics
+/// and what it was trained on.
+pub async fn models_page(State(state): State<AppState>) -> impl IntoResponse {
+    let models = state.inference.models();
+
+    let template = ModelsPage {
+        manifest_version: models.manifest().version.clone(),
+        class_labels: models.manifest().class_labels.clone(),
+        strategy: state.ensemble,
+        models: models.describe(),
+        training_data: models.manifest().training_data.clone(),
+    };
+
+    HtmlTemplate(template)
+}
@@ -0,0 +1,156 @@
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
+};
+use reqwest::StatusCode;
+
+use crate::{
+    audio::{canonical_path, read_canonical},
+    db::{
+        results::upload_uuid_from_name,
+        segments::{finish_segment_analysis, get_segment_analysis, save_segment, start_segment_analysis},
+    },
+    features::{FeatureExtractor, NormalizationStats},
+    http::{
+        handlers::{
+            track_menu::{upload_from_name, BUSY_RETRY_AFTER},
+            HtmlTemplate,
+        },
+        AppState,
+    },
+    ml::{
+        ensemble::Ensemble,
+        inference::InferenceError,
+        segments::{classify_segment, segment_bounds, Segment, SegmentAnalysis, SegmentBounds},
+    },
+};
+
+#[derive(Template)]
+#[template(path = "segments.html")]
+pub struct TrackSegments {
+    pub upload_name: String,
+    pub analysis: Option<SegmentAnalysis>,
+}
+
+/// Segment analysis of the track so far, refreshed by the page while it runs.
+pub async fn segment_report(Path(upload_name): Path<String>) -> Response {
+    let Some(upload_uuid) = upload_uuid_from_name(&upload_name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match get_segment_analysis(upload_uuid).await {
+        Ok(analysis) => HtmlTemplate(TrackSegments { upload_name, analysis }).into_response(),
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Starts classifying the whole track window by window in the background, unless an analysis
+/// is already running, and redirects to its page.
+pub async fn analyze_segments(
+    State(state): State<AppState>,
+    Path(upload_name): Path<String>,
+) -> Response {
+    // segments are classified under the stored name
+    let Some(upload) = upload_from_name(&upload_name).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let upload_uuid = upload.upload_uuid;
+
+    let server_data = env::var("SERVER_DATA").expect("SERVER_DATA env var not found");
+    let path = canonical_path(std::path::Path::new(&server_data), &upload_uuid);
+
+    let signal = match tokio::task::spawn_blocking(move || read_canonical(&path)).await {
+        Ok(Ok(signal)) => Arc::new(signal),
+        Ok(Err(e)) => {
+            tracing::warn!("Canonical audio of {} couldn't be read: {}", upload_uuid, e);
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "The decoded audio of this upload isn't available, upload the track again to analyse all of it",
+            )
+                .into_response();
+        }
+        Err(e) => {
+            tracing::error!("{}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let bounds = segment_bounds(signal.len());
+    let class_labels = state.inference.models().manifest().class_labels.clone();
+
+    match start_segment_analysis(&upload_uuid, &class_labels, state.ensemble, bounds.len()).await {
+        Ok(true) => {
+            tracing::info!("Analysing {} segments of {}", bounds.len(), upload_uuid);
+            tokio::spawn(run_segment_analysis(state.clone(), upload_uuid, upload_name.clone(), signal, bounds));
+        }
+        Ok(false) => tracing::info!("Segment analysis of {} is already running", upload_uuid),
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    Redirect::to(&format!("/track/{}/segments", upload_name)).into_response()
+}
+
+/// Classifies one segment after the other on the inference workers, storing each as it is done.
+/// Waits whenever the workers are busy, so interactive requests aren't starved.
+async fn run_segment_analysis(
+    state: AppState,
+    upload_uuid: String,
+    upload_name: String,
+    signal: Arc<Vec<f32>>,
+    bounds: Vec<SegmentBounds>,
+) {
+    let strategy: Ensemble = state.ensemble;
+    let extractor = Arc::new(Mutex::new(FeatureExtractor::new()));
+    let stats = Arc::new(NormalizationStats::from_env());
+    let retry_after = Duration::from_secs(BUSY_RETRY_AFTER.parse().unwrap_or(5));
+
+    for segment_bounds in bounds {
+        let segment = loop {
+            let (extractor, stats, signal, song_id) = (extractor.clone(), stats.clone(), signal.clone(), upload_name.clone());
+
+            let classified = state
+                .inference
+                .run(move |models| {
+                    let mut extractor = extractor.lock().expect("Extractor lock should not be poisoned");
+                    classify_segment(models, &mut extractor, &stats, &signal, &segment_bounds, strategy, &song_id)
+                })
+                .await;
+
+            match classified {
+                Ok(Ok(result)) => break Segment::from_result(&segment_bounds, &result),
+                Ok(Err(missing_features)) => break Segment::unclassified(&segment_bounds, missing_features),
+                Err(InferenceError::Busy) => tokio::time::sleep(retry_after).await,
+                Err(e) => {
+                    tracing::error!("Segment {} of {} failed: {}", segment_bounds.index, upload_uuid, e);
+                    let _ = finish_segment_analysis(&upload_uuid, Some(e.to_string())).await;
+                    return;
+                }
+            }
+        };
+
+        tracing::info!("Segment {} of {} analysed: {:?}", segment.index, upload_uuid, segment.major_class);
+
+        if let Err(e) = save_segment(&upload_uuid, &segment).await {
+            tracing::error!("{:?}", e);
+            let _ = finish_segment_analysis(&upload_uuid, Some("A segment couldn't be stored".to_string())).await;
+            return;
+        }
+    }
+
+    if let Err(e) = finish_segment_analysis(&upload_uuid, None).await {
+        tracing::error!("{:?}", e);
+    }
+}
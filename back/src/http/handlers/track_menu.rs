@@ -1,9 +1,34 @@
+use std::{env, io::Seek};
+
 use askama::Template;
-use axum::{extract::Path, response::IntoResponse};
+use axum::{body::Body, extract::{Path, Query, State}, http::{header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER}, HeaderValue}, response::{IntoResponse, Redirect, Response}, Form};
+use axum_extra::extract::CookieJar;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio_util::io::ReaderStream;
 
 use crate::{
-    http::handlers::HtmlTemplate,
-    ml::ml::{instantiate_models, Feature, FeatureDetail, SongClassificationResult},
+    bundle::{bundle_file_name, bundle_files, is_plain_file_name, write_bundle, BundleError},
+    db::{
+        db_conn::{get_upload, Upload},
+        results::{get_classification, save_classification, upload_uuid_from_name},
+        similarity::set_upload_shared,
+    },
+    http::{
+        handlers::{
+            api::{ClassificationResponse, API_SCHEMA_VERSION},
+            HtmlTemplate,
+        },
+        AppState,
+    },
+    ml::{
+        ablation::AblationReport,
+        ensemble::Ensemble,
+        inference::InferenceError,
+        ml::{find_signal_path, load_signal_from, sample_file_name, Feature, FeatureDetail, MissingFeature, SongClassificationResult},
+        provenance::DriftReport,
+        saliency::{occlusion, saliency_path},
+    },
 };
 
 #[derive(Template)]
@@ -11,25 +36,423 @@ use crate::{
 pub struct TrackMenu {
     pub song_classification_result: SongClassificationResult,
     pub upload_name: String,
+    pub upload_uuid: String,
+    pub sample_url: String,
     pub cum_class: Vec<String>,
-    pub features: Vec<FeatureDetail>
+    pub features: Vec<FeatureDetail>,
+    pub strategies: Vec<Ensemble>,
+    pub shared: bool,
 }
 
-pub async fn track_menu(Path(upload_name): Path<String>) -> impl IntoResponse {
-    let mut models = instantiate_models(Feature::all());
+#[derive(Template)]
+#[template(path = "classification_error.html")]
+pub struct ClassificationError {
+    pub upload_name: String,
+    pub busy: bool,
+    pub message: String,
+    pub missing_features: Vec<MissingFeature>,
+}
 
-    let song_classificaiton_result = SongClassificationResult::new(&mut models, upload_name.clone());
+#[derive(Template)]
+#[template(path = "verify.html")]
+pub struct VerifyTrack {
+    pub upload_name: String,
+    pub report: DriftReport,
+}
+
+#[derive(Template)]
+#[template(path = "ablation.html")]
+pub struct TrackAblation {
+    pub upload_name: String,
+    pub report: AblationReport,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TrackQuery {
+    pub strategy: Option<Ensemble>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ShareForm {
+    pub shared: bool,
+}
+
+pub async fn track_menu(
+    State(state): State<AppState>,
+    Path(upload_name): Path<String>,
+    Query(query): Query<TrackQuery>,
+) -> impl IntoResponse {
+    let Some(upload) = upload_from_name(&upload_name).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let upload_uuid = upload.upload_uuid.as_str();
+
+    let song_classificaiton_result = match stored_or_classify(&state, &upload).await {
+        Ok(result) => result.with_strategy(query.strategy.unwrap_or(state.ensemble)),
+        Err(e) => return inference_error_response(e, upload_name),
+    };
 
     let cum_class: Vec<String> = song_classificaiton_result.cum_classification.clone().iter().map(|x| format!("{:.2}%", x * 100.0)).collect();
 
     let features: Vec<FeatureDetail> = song_classificaiton_result.get_features_formatted_for_path();
 
     let template = TrackMenu {
+        upload_uuid: upload_uuid.to_string(),
+        sample_url: format!("/server_data/30s/{}", sample_file_name(&upload_name)),
         upload_name: upload_name,
         song_classification_result: song_classificaiton_result,
         cum_class: cum_class,
-        features: features
+        features: features,
+        strategies: Ensemble::all().to_vec(),
+        shared: upload.shared,
+    };
+
+    HtmlTemplate(template).into_response()
+}
+
+/// Discards the stored result and classifies the track again.
+pub async fn reclassify_track(
+    State(state): State<AppState>,
+    Path(upload_name): Path<String>,
+) -> impl IntoResponse {
+    let Some(upload) = upload_from_name(&upload_name).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    if let Err(e) = classify_and_store(&state, &upload).await {
+        return inference_error_response(e, upload_name);
+    }
+
+    Redirect::to(&format!("/track/{}", upload_name)).into_response()
+}
+
+/// Classifies the track again without storing it and compares the outcome with the stored result.
+pub async fn verify_track(
+    State(state): State<AppState>,
+    Path(upload_name): Path<String>,
+) -> impl IntoResponse {
+    let Some(upload) = upload_from_name(&upload_name).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let upload_uuid = upload.upload_uuid.as_str();
+
+    let stored = match get_classification(upload_uuid, &upload_name).await {
+        Ok(Some(stored)) => stored,
+        // nothing to compare with yet, the track page classifies and stores it
+        Ok(None) => return Redirect::to(&format!("/track/{}", upload_name)).into_response(),
+        Err(e) => {
+            tracing::error!("Stored classification couldn't be read: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let fresh = match state.inference.classify(upload_name.clone(), stored.strategy).await {
+        Ok(fresh) => fresh,
+        Err(e) => return inference_error_response(e, upload_name),
+    };
+
+    let report = DriftReport::new(&stored, &fresh);
+    tracing::info!("Verified {}, drifted: {}", upload_uuid, report.drifted);
+
+    HtmlTemplate(VerifyTrack { upload_name, report }).into_response()
+}
+
+/// Leave-one-feature-out view of the stored result, combined with the requested strategy.
+pub async fn ablation_report(
+    State(state): State<AppState>,
+    Path(upload_name): Path<String>,
+    Query(query): Query<TrackQuery>,
+) -> impl IntoResponse {
+    let Some(upload) = upload_from_name(&upload_name).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let result = match stored_or_classify(&state, &upload).await {
+        Ok(result) => result.with_strategy(query.strategy.unwrap_or(state.ensemble)),
+        Err(e) => return inference_error_response(e, upload_name),
+    };
+
+    let report = AblationReport::new(&result);
+
+    HtmlTemplate(TrackAblation { upload_name, report }).into_response()
+}
+
+/// Shares the track with every user's library-wide similar-track search, or stops sharing it.
+pub async fn share_track(
+    Path(upload_name): Path<String>,
+    jar: CookieJar,
+    Form(form): Form<ShareForm>,
+) -> impl IntoResponse {
+    let Some(upload_uuid) = upload_uuid_from_name(&upload_name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let Some(user_uuid) = jar.get("uuid") else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    match set_upload_shared(upload_uuid, user_uuid.value(), form.shared).await {
+        Ok(true) => Redirect::to(&format!("/track/{}", upload_name)).into_response(),
+        // not an upload of this user
+        Ok(false) => StatusCode::FORBIDDEN.into_response(),
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// PNG occlusion saliency of one feature model for the track's major class, computed on the
+/// inference workers the first time and cached under `SERVER_DATA/saliency/`.
+pub async fn saliency_image(
+    State(state): State<AppState>,
+    Path((upload_name, feature_key)): Path<(String, String)>,
+) -> Response {
+    let Some(feature) = Feature::from_key(&feature_key) else {
+        return StatusCode::NOT_FOUND.into_response();
     };
+    let Some(upload) = upload_from_name(&upload_name).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let upload_uuid = upload.upload_uuid.as_str();
+
+    let class = match stored_or_classify(&state, &upload).await {
+        Ok(result) => result.major_class.index,
+        Err(e) => {
+            tracing::warn!("{}", e);
+            return inference_error_status(&e).into_response();
+        }
+    };
+
+    let Some(model_sha256) = state.inference.models().model_sha256(&feature).map(|sha| sha.to_string()) else {
+        return (StatusCode::UNPROCESSABLE_ENTITY, format!("No {} model is loaded", feature.key())).into_response();
+    };
+
+    let server_data = env::var("SERVER_DATA").expect("SERVER_DATA env var not found");
+    let path = saliency_path(std::path::Path::new(&server_data), upload_uuid, &feature, class, &model_sha256);
+
+    if let Ok(png) = tokio::fs::read(&path).await {
+        return png_response(png);
+    }
+
+    let job_feature = feature.clone();
+    let job_name = upload_name.clone();
+    let png = state
+        .inference
+        .run(move |models| {
+            let input_shape = &models.manifest().entry(&job_feature).input_shape;
+            let frames = load_signal_from(&find_signal_path(&job_feature, job_name), &job_feature, input_shape)
+                .map_err(|e| e.to_string())?;
+
+            occlusion(models, &job_feature, &frames, class)
+                .and_then(|map| map.to_png())
+                .map_err(|e| e.to_string())
+        })
+        .await;
+
+    match png {
+        Ok(Ok(png)) => {
+            tracing::info!("Saliency of {} for {} computed", feature.key(), upload_uuid);
+
+            if let Some(dir) = path.parent() {
+                let _ = tokio::fs::create_dir_all(dir).await;
+            }
+            if let Err(e) = tokio::fs::write(&path, &png).await {
+                tracing::error!("Saliency image couldn't be cached at {}: {}", path.display(), e);
+            }
+
+            png_response(png)
+        }
+        Ok(Err(e)) => {
+            tracing::warn!("Saliency of {} for {} failed: {}", feature.key(), upload_uuid, e);
+            (StatusCode::UNPROCESSABLE_ENTITY, e).into_response()
+        }
+        Err(e) => {
+            tracing::warn!("{}", e);
+            let mut response = inference_error_status(&e).into_response();
+            if let InferenceError::Busy = e {
+                response.headers_mut().insert(RETRY_AFTER, HeaderValue::from_static(BUSY_RETRY_AFTER));
+            }
+            response
+        }
+    }
+}
+
+/// Zip of the upload's audio, 30 s sample, feature arrays, ETL videos and its full stored
+/// classification, for archiving an analysis.
+pub async fn download_bundle(
+    State(state): State<AppState>,
+    Path(upload_name): Path<String>,
+) -> Response {
+    let Some(upload) = upload_from_name(&upload_name).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let upload_uuid = upload.upload_uuid.as_str();
+
+    let classification = match stored_or_classify(&state, &upload).await {
+        Ok(result) => result,
+        Err(e) => return inference_error_response(e, upload_name),
+    };
+
+    let classification_json = match serde_json::to_vec_pretty(&ClassificationResponse {
+        schema_version: API_SCHEMA_VERSION,
+        upload_uuid: upload_uuid.to_string(),
+        classification,
+    }) {
+        Ok(json) => json,
+        Err(e) => {
+            tracing::error!("Classification couldn't be serialized: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let Ok(permit) = state.bundles.clone().try_acquire_owned() else {
+        let mut response = StatusCode::SERVICE_UNAVAILABLE.into_response();
+        response.headers_mut().insert(RETRY_AFTER, HeaderValue::from_static(BUSY_RETRY_AFTER));
+        return response;
+    };
+
+    let server_data = env::var("SERVER_DATA").expect("SERVER_DATA env var not found");
+    let files = bundle_files(std::path::Path::new(&server_data), &upload_name);
+
+    // reading and compressing the files is blocking, keep it off the async workers. The zip
+    // goes to an unnamed temporary file, removed once the response is streamed and it's closed.
+    let job_name = upload_name.clone();
+    let bundle = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let file = tempfile::tempfile().map_err(|e| BundleError::Io(e.to_string()))?;
+        let (mut file, manifest) = write_bundle(file, &job_name, &files, &classification_json)?;
+        let size = file.stream_position().map_err(|e| BundleError::Io(e.to_string()))?;
+        file.rewind().map_err(|e| BundleError::Io(e.to_string()))?;
+
+        Ok::<_, BundleError>((file, size, manifest))
+    })
+    .await
+    .expect("Bundle task shouldn't panic");
+
+    match bundle {
+        Ok((zip, size, manifest)) => {
+            tracing::info!(
+                "Bundle of {} written with {} files ({} bytes), missing: {:?}",
+                upload_uuid,
+                manifest.files.len(),
+                size,
+                manifest.missing
+            );
+
+            let body = Body::from_stream(ReaderStream::new(tokio::fs::File::from_std(zip)));
+            let disposition = format!("attachment; filename=\"{}\"", bundle_file_name(&upload_name));
+            let mut response = (
+                [
+                    (CONTENT_TYPE, HeaderValue::from_static("application/zip")),
+                    (CONTENT_LENGTH, HeaderValue::from(size)),
+                ],
+                body,
+            )
+                .into_response();
+            if let Ok(disposition) = HeaderValue::from_str(&disposition) {
+                response.headers_mut().insert(CONTENT_DISPOSITION, disposition);
+            }
+            response
+        }
+        Err(e) => {
+            tracing::error!("Bundle of {} failed: {}", upload_uuid, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn png_response(png: Vec<u8>) -> Response {
+    ([(CONTENT_TYPE, HeaderValue::from_static("image/png"))], png).into_response()
+}
+
+/// The upload a url's `<upload_uuid>-<file_name>` names. `None` unless the name is exactly the
+/// stored one, so nothing the url adds ends up in a path.
+pub async fn upload_from_name(upload_name: &str) -> Option<Upload> {
+    let upload_uuid = upload_uuid_from_name(upload_name)?;
+
+    let upload = match get_upload(upload_uuid.to_string()).await {
+        Ok(upload) => upload,
+        Err(e) => {
+            tracing::info!("{:?}", e);
+            return None;
+        }
+    };
+
+    let stored_name = upload.upload_name();
+    if stored_name != upload_name || !is_plain_file_name(&stored_name) {
+        tracing::warn!("Upload {} asked for as {:?}", stored_name, upload_name);
+        return None;
+    }
+
+    Some(upload)
+}
+
+/// Returns the stored result for the upload, classifying it first if there's none yet.
+pub async fn stored_or_classify(state: &AppState, upload: &Upload) -> Result<SongClassificationResult, InferenceError> {
+    match get_classification(&upload.upload_uuid, &upload.upload_name()).await {
+        Ok(Some(stored)) => {
+            tracing::info!("Serving stored classification for {}", upload.upload_uuid);
+            Ok(stored)
+        }
+        Ok(None) => classify_and_store(state, upload).await,
+        Err(e) => {
+            tracing::error!("Stored classification couldn't be read: {:?}", e);
+            classify_and_store(state, upload).await
+        }
+    }
+}
+
+/// Features are read from `SERVER_DATA/features/` under the upload's stored name.
+async fn classify_and_store(state: &AppState, upload: &Upload) -> Result<SongClassificationResult, InferenceError> {
+    let upload_name = upload.upload_name();
+    if !is_plain_file_name(&upload_name) {
+        return Err(InferenceError::InvalidUpload(upload_name));
+    }
+
+    let result = state.inference.classify(upload_name, state.ensemble).await?;
+
+    if let Err(e) = save_classification(&upload.upload_uuid, &result).await {
+        tracing::error!("Classification couldn't be stored: {:?}", e);
+    }
+
+    Ok(result)
+}
+
+/// 503 when the workers are saturated, 422 when none of the features are available,
+/// 500 when the classification failed, 404 for an upload without a usable name.
+pub fn inference_error_status(e: &InferenceError) -> StatusCode {
+    match e {
+        InferenceError::Busy => StatusCode::SERVICE_UNAVAILABLE,
+        InferenceError::NoFeatures(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        InferenceError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        InferenceError::InvalidUpload(_) => StatusCode::NOT_FOUND,
+    }
+}
+
+/// Seconds a client is asked to wait before retrying a busy response.
+pub const BUSY_RETRY_AFTER: &str = "5";
+
+pub fn inference_error_response(e: InferenceError, upload_name: String) -> Response {
+    tracing::warn!("{}", e);
+
+    let template = ClassificationError {
+        upload_name,
+        busy: matches!(e, InferenceError::Busy),
+        message: match &e {
+            InferenceError::NoFeatures(_) => "None of the track's features could be classified.".to_string(),
+            _ => e.to_string(),
+        },
+        missing_features: match &e {
+            InferenceError::NoFeatures(missing) => missing.clone(),
+            _ => Vec::new(),
+        },
+    };
+
+    let mut response = (inference_error_status(&e), HtmlTemplate(template)).into_response();
+    if let InferenceError::Busy = e {
+        response.headers_mut().insert(RETRY_AFTER, HeaderValue::from_static(BUSY_RETRY_AFTER));
+    }
 
-    HtmlTemplate(template)
+    response
 }
@@ -1,13 +1,17 @@
 use std::{env, path::Path};
 
 use askama::Template;
-use axum::extract::Multipart;
+use axum::{extract::Multipart, response::{IntoResponse, Response}};
 use axum_extra::extract::CookieJar;
 use reqwest::StatusCode;
 use tokio::{fs::File, io::AsyncWriteExt};
 use tracing::{debug, info};
 
-use crate::{db::db_conn::{get_user_by_uuid, insert_upload_to_db}, http::handlers::HtmlTemplate};
+use crate::{
+    audio::{canonical_path, canonicalize, AudioError, AudioInfo},
+    db::db_conn::{delete_upload_db, get_user_by_uuid, insert_upload_to_db},
+    http::handlers::HtmlTemplate,
+};
 
 
 
@@ -17,12 +21,20 @@ use crate::{db::db_conn::{get_user_by_uuid, insert_upload_to_db}, http::handlers
         title: String,
         upload_uuid: String,
         bytes: usize,
+        audio: Option<AudioInfo>,
     }
 
+#[derive(Template)]
+#[template(path = "upload_rejected.html")]
+pub struct UploadRejected {
+    pub file_name: String,
+    pub message: String,
+}
+
 pub async fn upload_track(
     jar: CookieJar,
     mut multipart: Multipart,
-) -> Result<HtmlTemplate<UploadTemplate>, StatusCode> {
+) -> Result<HtmlTemplate<UploadTemplate>, Response> {
     if let Some(uuid) = jar.get("uuid") {
         let user = get_user_by_uuid(&uuid.value().to_string()).await.unwrap();
 
@@ -30,6 +42,7 @@ pub async fn upload_track(
             upload_uuid: "".to_string(),
             title: "".to_string(),
             bytes: 0,
+            audio: None,
         };
 
         while let Some(field) = multipart.next_field().await.unwrap() {
@@ -69,12 +82,35 @@ pub async fn upload_track(
 
             match *suffix {
                 "mp3" | "wav" => println!("file is .wav or mp3"),
-                _ => return Err(StatusCode::FORBIDDEN),
+                _ => return Err(StatusCode::FORBIDDEN.into_response()),
             }
 
             let data = field.bytes().await.unwrap();
 
-            let upload = insert_upload_to_db(&user.uuid, &file_name_normalized).await;
+            // decoding is CPU bound, keep it off the async workers
+            let bytes = data.to_vec();
+            let extension = suffix.to_string();
+            let canonical = tokio::task::spawn_blocking(move || {
+                let canonical = canonicalize(bytes, &extension)?;
+                let wav = canonical.to_wav()?;
+                Ok::<_, AudioError>((canonical.info, wav))
+            })
+            .await
+            .expect("Decoding task shouldn't panic");
+
+            let (audio, wav) = match canonical {
+                Ok(canonical) => canonical,
+                Err(e) => {
+                    tracing::warn!("Upload {} rejected: {}", file_name_normalized, e);
+                    let template = UploadRejected {
+                        file_name: file_name_normalized,
+                        message: e.to_string(),
+                    };
+                    return Err((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(template)).into_response());
+                }
+            };
+
+            let upload = insert_upload_to_db(&user.uuid, &file_name_normalized, &audio).await;
             tracing::info!("UPLOADING");
             
             
@@ -87,16 +123,33 @@ pub async fn upload_track(
                     upload.file_name,
                 )
             );
-            let mut file = File::create(format!(
+            let upload_file = format!(
                 "{}/{}-{}",
                 dir.display(),
                 upload.upload_uuid,
                 upload.file_name
-            ))
-            .await
-            .expect("Should create file under specified path");
+            );
+            let mut file = File::create(&upload_file)
+                .await
+                .expect("Should create file under specified path");
 
             let _ = file.write_all(&data).await.unwrap();
+
+            let canonical_file = canonical_path(Path::new(&server_data), &upload.upload_uuid);
+            if let Err(e) = tokio::fs::write(&canonical_file, &wav).await {
+                // every later step reads the canonical audio, don't keep an upload without it
+                tracing::error!("Canonical audio couldn't be written to {}: {}", canonical_file.display(), e);
+                let _ = tokio::fs::remove_file(&upload_file).await;
+                delete_upload_db(upload.upload_uuid, upload.user_uuid).await;
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            info!(
+                "Canonical audio written to {}: {:.1} s, originally {} Hz with {} channels",
+                canonical_file.display(),
+                audio.duration_seconds,
+                audio.sample_rate,
+                audio.channels
+            );
             
             info!("Starting request for processing!");
 
@@ -113,10 +166,11 @@ pub async fn upload_track(
             template.bytes = data.len();
             template.title = upload.file_name;
             template.upload_uuid = upload.upload_uuid;
+            template.audio = Some(audio);
         }
 
         Ok(HtmlTemplate(template))
     } else {
-        Err(StatusCode::CONFLICT)
+        Err(StatusCode::CONFLICT.into_response())
     }
 }
\ No newline at end of file
@@ -1 +1,17 @@
-pub mod handlers;
\ No newline at end of file
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+
+use crate::ml::ensemble::Ensemble;
+use crate::ml::inference::InferencePool;
+
+pub mod handlers;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub inference: InferencePool,
+    /// Used when a request doesn't pick a strategy with `?strategy=`.
+    pub ensemble: Ensemble,
+    /// Permits for writing upload bundles, see `bundle::bundle_builds_from_env`.
+    pub bundles: Arc<Semaphore>,
+}
\ No newline at end of file
@@ -1,7 +1,11 @@
 
+mod audio;
+mod bundle;
 mod db;
+mod features;
 mod http;
 mod ml;
+mod offline;
 
 pub mod config {
 
@@ -37,6 +41,7 @@ pub mod config {
         let folders = [
             "",
             "30s",
+            "canonical",
             "cqt",
             "ft",
             "mfcc",
@@ -46,6 +51,7 @@ pub mod config {
             "features",
             "mel_spect",
             "power_spectr",
+            "saliency",
             "stft",
             "uploads",
         ];
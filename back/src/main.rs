@@ -1,12 +1,18 @@
 extern crate dotenv;
 
+mod audio;
+mod bundle;
 mod db;
+mod features;
 mod http;
 mod ml;
+mod offline;
 
 
 use back::config::{self, create_server_data_dirs, create_upload_dir, start_4hourly_task};
 
+use std::sync::Arc;
+
 use axum::extract::DefaultBodyLimit;
 use db::db_conn::{self};
 use dotenv::dotenv;
@@ -18,11 +24,22 @@ use axum::Router;
 
 use tracing_subscriber::fmt;
 
+use crate::bundle::bundle_builds_from_env;
+use crate::http::handlers::admin::reload_models;
+use crate::http::handlers::api::{compare_classifications, models_info, track_ablation, track_classification, track_segments, track_similar, track_timeline};
+use crate::http::handlers::compare::compare_tracks;
 use crate::http::handlers::delete::delete_upload;
+use crate::http::handlers::models::models_page;
 use crate::http::handlers::profile::get_user_data;
 use crate::http::handlers::register::{register_user, user_form, user_registered};
-use crate::http::handlers::track_menu::track_menu;
+use crate::http::handlers::segments::{analyze_segments, segment_report};
+use crate::http::handlers::track_menu::{ablation_report, download_bundle, reclassify_track, saliency_image, share_track, track_menu, verify_track};
 use crate::http::handlers::upload::upload_track;
+use crate::http::AppState;
+use crate::ml::ensemble::Ensemble;
+use crate::ml::inference::InferencePool;
+use crate::ml::manifest::ModelManifest;
+use crate::ml::ml::ModelRegistry;
 
 #[allow(unused)]
 #[tokio::main]
@@ -32,6 +49,16 @@ async fn main() -> Result<(), Box<std::io::Error>> {
     let subscriber = fmt().with_line_number(true).with_file(true).finish();
 
     tracing::subscriber::set_global_default(subscriber).expect("setting tracing default failed");
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        if let Err(e) = offline::run_command(command, &args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let pool = db_conn::get_pool().await;
 
     sqlx::migrate!("./migrations")
@@ -53,7 +80,25 @@ async fn main() -> Result<(), Box<std::io::Error>> {
     start_4hourly_task().await;
 
 
-    let app = app()
+    let manifest = ModelManifest::from_env().expect("Model manifest should be valid");
+    tracing::info!("Model manifest version {} loaded", manifest.version);
+
+    let models = ModelRegistry::load(manifest).expect("Models should match the manifest");
+    tracing::info!("Models loaded");
+
+    let ensemble = Ensemble::from_env().unwrap_or_else(|e| panic!("{}", e));
+    tracing::info!("Default ensemble strategy: {}", ensemble.key());
+
+    let inference = InferencePool::from_env(Arc::new(models));
+    inference.watch_from_env();
+
+    let state = AppState {
+        inference,
+        ensemble,
+        bundles: bundle_builds_from_env(),
+    };
+
+    let app = app(state)
         .layer(config::create_cors_layers())
         .layer(DefaultBodyLimit::max(250 * 1024 * 1024));
 
@@ -65,7 +110,7 @@ async fn main() -> Result<(), Box<std::io::Error>> {
     Ok(())
 }
 
-fn app() -> Router {
+fn app(state: AppState) -> Router {
     tracing::info!("SETUP - CREATING ENDPOINTS");
     Router::new()
         .route("/", get(user_form))
@@ -74,9 +119,27 @@ fn app() -> Router {
         .route("/upload", post(upload_track))
         .route("/delete/{upload_uuid}", post(delete_upload))
         .route("/track/{upload_name}", get(track_menu))
+        .route("/track/{upload_name}/reclassify", post(reclassify_track))
+        .route("/track/{upload_name}/verify", post(verify_track))
+        .route("/track/{upload_name}/share", post(share_track))
+        .route("/track/{upload_name}/ablation", get(ablation_report))
+        .route("/track/{upload_name}/segments", get(segment_report).post(analyze_segments))
+        .route("/track/{upload_name}/bundle", get(download_bundle))
+        .route("/track/{upload_name}/saliency/{feature}", get(saliency_image))
+        .route("/compare", get(compare_tracks))
+        .route("/models", get(models_page))
+        .route("/api/tracks/{upload_uuid}/classification", get(track_classification))
+        .route("/api/tracks/{upload_uuid}/timeline", get(track_timeline))
+        .route("/api/tracks/{upload_uuid}/similar", get(track_similar))
+        .route("/api/tracks/{upload_uuid}/ablation", get(track_ablation))
+        .route("/api/tracks/{upload_uuid}/segments", get(track_segments))
+        .route("/api/compare", get(compare_classifications))
+        .route("/api/models", get(models_info))
+        .route("/admin/models/reload", post(reload_models))
         
         .nest_service("/server_data", ServeDir::new(
             std::env::var("SERVER_DATA").unwrap()))
         .nest_service("/static", ServeDir::new("static"))
+        .with_state(state)
 }
 
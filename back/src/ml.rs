@@ -4,7 +4,6 @@ pub mod ml {
     // TODO
     //    http:
     //    - classification results
-    //    - model used and training data
     //    - h ow classification was doen
     //    - what the feature is
     //    - how the feature was extracted
@@ -18,41 +17,260 @@ pub mod ml {
         path::{Path, PathBuf},
     };
 
-    use ndarray::{array, Array2, Array3, ArrayBase, OwnedRepr};
+    use ndarray::{array, Array1, Array2, Array3, ArrayBase, Axis, OwnedRepr};
     use ndarray_npy::{ReadNpyError, ReadNpyExt, WriteNpyError};
-    use tch::{nn::ModuleT, CModule, Kind, Tensor};
+    use serde::{Deserialize, Serialize};
+    use sha2::{Digest, Sha256};
 
     use crate::db;
+    use crate::ml::backend::{self, ModelBackend, ModelParameter};
+    use crate::ml::ensemble::{argmax, Ensemble};
+    use crate::ml::manifest::{Calibration, ManifestError, ModelManifest};
+    use crate::ml::provenance::Provenance;
 
     fn load_signal(track_id: String) {}
 
-    #[derive(Debug)]
-    pub enum Class {
-        Rock,
-        HipHop,
-        Electronic,
-        Pop,
-        Classical,
+    /// Framing used by the ETL when it cuts the 30 s sample into model inputs.
+    pub const SAMPLE_RATE: u32 = 22050;
+    pub const FRAME_LENGTH: u32 = 22050;
+    pub const FRAME_HOP: u32 = 2205;
+
+    /// How confident the ensemble is about `major_class`.
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    pub struct Uncertainty {
+        /// Shannon entropy of the song-level distribution, in nats.
+        pub entropy: f32,
+        /// Entropy divided by its maximum, `ln(class count)`; 0 is certain, 1 is uniform.
+        pub normalized_entropy: f32,
+        /// Difference between the top-1 and top-2 probabilities.
+        pub margin: f32,
+        /// Feature models whose own argmax is the ensemble winner.
+        pub agreeing_features: usize,
+        pub feature_count: usize,
+        /// Share of feature models disagreeing with the ensemble winner.
+        pub disagreement: f32,
+        /// Set when `margin` is below `UNCERTAINTY_MARGIN`.
+        pub uncertain: bool,
+    }
+
+    impl Uncertainty {
+        pub fn new(
+            cum_classification: &[f32],
+            classifications: &[FeatureClassificationResult],
+            major_class: &Class,
+            margin_threshold: f32,
+        ) -> Self {
+            let entropy: f32 = -cum_classification
+                .iter()
+                .filter(|p| **p > 0.0)
+                .map(|p| p * p.ln())
+                .sum::<f32>();
+
+            let normalized_entropy = if cum_classification.len() > 1 {
+                entropy / (cum_classification.len() as f32).ln()
+            } else {
+                0.0
+            };
+
+            let mut sorted = cum_classification.to_vec();
+            sorted.sort_by(|a, b| b.total_cmp(a));
+            let margin = match sorted.as_slice() {
+                [first, second, ..] => first - second,
+                [first] => *first,
+                [] => 0.0,
+            };
+
+            let agreeing_features = classifications
+                .iter()
+                .filter(|c| argmax(&c.avg_classification) == major_class.index)
+                .count();
+
+            let feature_count = classifications.len();
+
+            let disagreement = if feature_count > 0 {
+                1.0 - agreeing_features as f32 / feature_count as f32
+            } else {
+                0.0
+            };
+
+            Self {
+                entropy,
+                normalized_entropy,
+                margin,
+                agreeing_features,
+                feature_count,
+                disagreement,
+                uncertain: margin < margin_threshold,
+            }
+        }
+
+        /// Margin below which results are flagged, `UNCERTAINTY_MARGIN` or 0.1 when unset.
+        pub fn margin_threshold() -> f32 {
+            std::env::var("UNCERTAINTY_MARGIN")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0.1)
+        }
+    }
+
+    /// Probability relative to the top genre from which a genre counts as co-primary ("and").
+    pub const CO_PRIMARY_RATIO: f32 = 0.75;
+
+    /// How strongly a genre is present compared to the top genre.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum GenreRole {
+        /// The top genre.
+        Primary,
+        /// At least `CO_PRIMARY_RATIO` of the top genre's probability.
+        CoPrimary,
+        /// Above the threshold, but well behind the top genre.
+        Elements,
+    }
+
+    /// A genre above the secondary genre threshold.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct RankedGenre {
+        pub class: Class,
+        pub probability: f32,
+        /// Probability divided by the top genre's.
+        pub relative_confidence: f32,
+        pub role: GenreRole,
+    }
+
+    /// Every genre above `threshold` by descending probability, for tracks mixing several genres.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct Genres {
+        pub threshold: f32,
+        pub ranked: Vec<RankedGenre>,
+        /// e.g. "Primarily Rock and Electronic, with elements of Pop".
+        pub summary: String,
+    }
+
+    impl Genres {
+        /// The top genre is always listed, even when it is below `threshold`.
+        pub fn new(cum_classification: &[f32], class_labels: &[String], threshold: f32) -> Self {
+            let mut order: Vec<usize> = (0..cum_classification.len().min(class_labels.len())).collect();
+            // stable, so ties keep the `class_labels` order like `argmax`
+            order.sort_by(|a, b| cum_classification[*b].total_cmp(&cum_classification[*a]));
+
+            let top = order.first().map(|idx| cum_classification[*idx]).unwrap_or(0.0);
+
+            let ranked: Vec<RankedGenre> = order
+                .into_iter()
+                .enumerate()
+                .filter(|(rank, idx)| *rank == 0 || cum_classification[*idx] >= threshold)
+                .map(|(rank, idx)| {
+                    let probability = cum_classification[idx];
+                    let relative_confidence = if top > 0.0 { probability / top } else { 0.0 };
+
+                    let role = if rank == 0 {
+                        GenreRole::Primary
+                    } else if relative_confidence >= CO_PRIMARY_RATIO {
+                        GenreRole::CoPrimary
+                    } else {
+                        GenreRole::Elements
+                    };
+
+                    RankedGenre {
+                        class: Class {
+                            index: idx,
+                            label: class_labels[idx].clone(),
+                        },
+                        probability,
+                        relative_confidence,
+                        role,
+                    }
+                })
+                .collect();
+
+            let summary = Self::summarize(&ranked);
+
+            Self {
+                threshold,
+                ranked,
+                summary,
+            }
+        }
+
+        fn summarize(ranked: &[RankedGenre]) -> String {
+            let (primary, elements): (Vec<&RankedGenre>, Vec<&RankedGenre>) =
+                ranked.iter().partition(|g| g.role != GenreRole::Elements);
+
+            if primary.is_empty() {
+                return String::new();
+            }
+
+            let mut summary = format!("Primarily {}", join_labels(&primary));
+            if !elements.is_empty() {
+                summary.push_str(&format!(", with elements of {}", join_labels(&elements)));
+            }
+
+            summary
+        }
+
+        /// Probability a genre needs to be listed, `SECONDARY_GENRE_THRESHOLD` or 0.15 when unset.
+        pub fn threshold() -> f32 {
+            std::env::var("SECONDARY_GENRE_THRESHOLD")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0.15)
+        }
+    }
+
+    /// "A", "A and B", "A, B and C".
+    fn join_labels(genres: &[&RankedGenre]) -> String {
+        let labels: Vec<&str> = genres.iter().map(|g| g.class.label.as_str()).collect();
+
+        match labels.as_slice() {
+            [] => String::new(),
+            [only] => only.to_string(),
+            [init @ .., last] => format!("{} and {}", init.join(", "), last),
+        }
+    }
+
+    /// Ensemble probabilities of a single frame, `time` being the frame centre in seconds.
+    #[derive(Debug, Serialize, PartialEq)]
+    pub struct TimelineFrame {
+        pub time: f32,
+        pub probabilities: Vec<f32>,
+    }
+
+    /// A genre, identified by its position in the manifest's `class_labels`.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct Class {
+        pub index: usize,
+        pub label: String,
     }
 
     impl Display for Class {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            match self {
-                Class::Rock => write!(f, "{}", "Rock"),
-                Class::HipHop => write!(f, "{}", "Hip-Hop"),
-                Class::Electronic => write!(f, "{}", "Electronic"),
-                Class::Pop => write!(f, "{}", "Pop"),
-                Class::Classical => write!(f, "{}", "Classical"),
-            }
+            write!(f, "{}", self.label)
         }
     }
 
+    /// A feature left out of the ensemble, e.g. because its npy file or model is unavailable.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct MissingFeature {
+        pub feature: Feature,
+        pub reason: String,
+    }
+
 
 
     #[derive(Debug)]
-    pub struct CustomError(String);
+    pub struct CustomError(pub String);
+
+    impl Display for CustomError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl Error for CustomError {}
 
-    #[derive(Hash, Eq, PartialEq, Debug, Clone)]
+    #[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
     pub enum Feature {
         Ft,
         Mfcc,
@@ -94,43 +312,188 @@ pub mod ml {
                 Feature::PowerSpectrogram,
             ]
         }
+
+        /// Stable name used in the manifest and in the database.
+        pub fn key(&self) -> &'static str {
+            match self {
+                Feature::Ft => "ft",
+                Feature::Mfcc => "mfcc",
+                Feature::ChromaCens => "chroma_cens",
+                Feature::ChromaCqt => "chroma_cqt",
+                Feature::ChromaStft => "chroma_stft",
+                Feature::Spectrogram => "spectrogram",
+                Feature::PowerSpectrogram => "power_spectrogram",
+                Feature::MelSpectrogram => "mel_spectrogram",
+                Feature::Tonnetz => "tonnetz",
+            }
+        }
+
+        pub fn from_key(key: &str) -> Option<Feature> {
+            Feature::all().into_iter().find(|f| f.key() == key)
+        }
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Serialize)]
     pub struct SongClassificationResult {
         pub audio_title: String,
+        pub class_labels: Vec<String>,
         pub feature_classification_result: Vec<FeatureClassificationResult>,
+        pub strategy: Ensemble,
         pub cum_classification: Vec<f32>,
         pub major_class: Class,
+        /// `major_class` and the secondary genres above `Genres::threshold`.
+        pub genres: Genres,
+        pub uncertainty: Uncertainty,
+        /// Features skipped for this song; the weights of the remaining ones are renormalized.
+        pub missing_features: Vec<MissingFeature>,
+        /// Models and inputs the result was computed from; `None` for results stored before it was recorded.
+        pub provenance: Option<Provenance>,
     }
 
     impl SongClassificationResult {
-        pub fn new(instantiated_models: &mut HashMap<Feature, CModule>, song_id: String) -> Self {
-            let features = Feature::all();
-            
-            let classifications: Vec<FeatureClassificationResult> = features
-            .iter()
-            .map(|feature| FeatureClassificationResult::new(instantiated_models, &feature, song_id.clone()))
-            .collect();
+        /// Classifies every available feature, failing only when none of them could be classified.
+        pub fn new(models: &ModelRegistry, song_id: String, strategy: Ensemble) -> Result<Self, Vec<MissingFeature>> {
+            Self::from_features_path(models, &find_features_path(&song_id), song_id, strategy)
+        }
+
+        /// Same as `new`, reading the feature files from `features_path` instead of `SERVER_DATA`.
+        pub fn from_features_path(
+            models: &ModelRegistry,
+            features_path: &Path,
+            song_id: String,
+            strategy: Ensemble,
+        ) -> Result<Self, Vec<MissingFeature>> {
+            let mut classifications: Vec<FeatureClassificationResult> = Vec::new();
+            let mut missing_features: Vec<MissingFeature> = Vec::new();
+
+            for feature in Feature::all() {
+                match FeatureClassificationResult::from_features_path(models, &feature, features_path) {
+                    Ok(classification) => classifications.push(classification),
+                    Err(e) => {
+                        tracing::warn!("{:?} skipped for {}: {}", feature, song_id, e);
+                        missing_features.push(MissingFeature {
+                            feature,
+                            reason: e.to_string(),
+                        });
+                    }
+                }
+            }
+
+            Self::from_classifications(models, song_id, classifications, missing_features, strategy)
+        }
 
-            let cum_classification: Vec<f32> = SongClassificationResult::get_cum_classification(&classifications);
+        /// Combines freshly classified features and records their provenance, failing when there are none.
+        pub fn from_classifications(
+            models: &ModelRegistry,
+            song_id: String,
+            classifications: Vec<FeatureClassificationResult>,
+            missing_features: Vec<MissingFeature>,
+            strategy: Ensemble,
+        ) -> Result<Self, Vec<MissingFeature>> {
+            if classifications.is_empty() {
+                return Err(missing_features);
+            }
+
+            let provenance = Provenance::new(models, &classifications, strategy);
+
+            let mut result = Self::from_features(
+                song_id,
+                models.manifest().class_labels.clone(),
+                classifications,
+                missing_features,
+                strategy,
+            );
+            result.provenance = Some(provenance);
+
+            Ok(result)
+        }
+
+        /// Builds the song result from already computed per-feature results,
+        /// e.g. ones read back from the database.
+        pub fn from_features(
+            song_id: String,
+            class_labels: Vec<String>,
+            classifications: Vec<FeatureClassificationResult>,
+            missing_features: Vec<MissingFeature>,
+            strategy: Ensemble,
+        ) -> Self {
+            let cum_classification: Vec<f32> = strategy.combine(&classifications);
+
+            let major_class = SongClassificationResult::get_major_class(&cum_classification, &class_labels).expect("Should be valid at this point");
+
+            let uncertainty = Uncertainty::new(
+                &cum_classification,
+                &classifications,
+                &major_class,
+                Uncertainty::margin_threshold(),
+            );
 
-            let major_class = SongClassificationResult::get_major_class(&cum_classification).expect("Should be valid at this point");
+            let genres = Genres::new(&cum_classification, &class_labels, Genres::threshold());
 
             Self {
                 audio_title: song_id,
+                class_labels: class_labels,
                 feature_classification_result: classifications,
+                strategy: strategy,
                 cum_classification: cum_classification,
-                major_class: major_class
+                major_class: major_class,
+                genres,
+                uncertainty: uncertainty,
+                missing_features: missing_features,
+                provenance: None,
+            }
+
+
+        }
+
+        /// Recombines the per-feature results with another strategy, without running the models.
+        pub fn with_strategy(self, strategy: Ensemble) -> Self {
+            if self.strategy == strategy {
+                return self;
             }
 
+            let mut result = Self::from_features(
+                self.audio_title,
+                self.class_labels,
+                self.feature_classification_result,
+                self.missing_features,
+                strategy,
+            );
+            result.provenance = self.provenance;
+
+            result
+        }
 
+        /// Every frame's features combined with the result's strategy.
+        pub fn get_timeline(&self) -> Vec<TimelineFrame> {
+            let frame_count = self
+                .feature_classification_result
+                .iter()
+                .map(|f| f.per_frame_classifications.len())
+                .min()
+                .unwrap_or(0);
+
+            (0..frame_count as i64)
+                .map(|frame| {
+                    let rows: Vec<(&[f32], f32)> = self
+                        .feature_classification_result
+                        .iter()
+                        .map(|f| (f.per_frame_classifications[&frame].as_slice(), f.feature_weight))
+                        .collect();
+
+                    TimelineFrame {
+                        time: (frame as u32 * FRAME_HOP + FRAME_LENGTH / 2) as f32 / SAMPLE_RATE as f32,
+                        probabilities: self.strategy.combine_rows(&rows),
+                    }
+                })
+                .collect()
         }
 
         pub fn get_features_formatted_for_path(&self) -> Vec<FeatureDetail> {
             
             self.feature_classification_result.iter().map(|f| {
                 FeatureDetail {
+                    key: f.feature.key().to_string(),
                     folder: FeatureDetail::get_folder(&f.feature),
                     name: FeatureDetail::get_name(&f.feature),
                     short_desc: FeatureDetail::provide_details(&f.feature)
@@ -139,53 +502,23 @@ pub mod ml {
 
         }
 
-        fn get_cum_classification(classifications: &Vec<FeatureClassificationResult>) -> Vec<f32> {
-
-                let mut base: [f32; 5] = [0.0; 5];
-
-                let mut total_weight: f32 = 0.0;
-
-                let len = classifications.len().clone() as f32;
-
-                for classification in classifications {
-                    
-
-                    let cum_class = classification.avg_classification.clone();
-
-                    cum_class.iter().enumerate().for_each(|(idx, f)| base[idx] += f.mul(&classification.feature_weight));
-
-                    total_weight += classification.feature_weight;
-                }
-    
-                let res: Vec<f32> = dbg!(base).iter().map(|val| dbg!(val) / dbg!(total_weight)).collect();
-                dbg!(res)
-            
-        }
-
-        fn get_major_class(cum_classifications: &Vec<f32>) -> Result<Class, CustomError> {
-            let mut biggest_idx: usize = 0;
-            let mut biggest_val: f32 = 0.0;
-            let _ = cum_classifications.iter().enumerate().for_each(|(idx, class_percent)| {
-                if &class_percent >= &&biggest_val {
-                    dbg!("class >= &biggest val!\nclass:{:?},biggest_val:{:?}", &class_percent, &biggest_val);
-                    biggest_idx = idx;
-                    biggest_val = *class_percent;
-                }
-            });
+        fn get_major_class(cum_classifications: &Vec<f32>, class_labels: &[String]) -> Result<Class, CustomError> {
+            let biggest_idx = argmax(cum_classifications);
 
-             match biggest_idx {
-                0 => Ok(Class::Rock),
-                1 => Ok(Class::HipHop),
-                2 => Ok(Class::Electronic),
-                3 => Ok(Class::Pop),
-                4 => Ok(Class::Classical),
-                _ => Err(CustomError("Classification unsuccesfull, wrong major class calculation".to_string()))
+            match class_labels.get(biggest_idx) {
+                Some(label) => Ok(Class {
+                    index: biggest_idx,
+                    label: label.clone(),
+                }),
+                None => Err(CustomError("Classification unsuccesfull, wrong major class calculation".to_string()))
             }
         }
     }
 
     
     pub struct FeatureDetail {
+        /// `Feature::key`, used in URLs.
+        pub key: String,
         pub folder: String,
         pub name: String,
         pub short_desc: String
@@ -330,66 +663,103 @@ pub mod ml {
         }
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Serialize)]
     pub struct FeatureClassificationResult {
         pub feature: Feature,
         pub feature_weight: f32,
         pub avg_classification: Vec<f32>,
         pub weighted_avg_classification: Vec<f32>,
+        #[serde(skip)]
         pub avg_classification_string: Vec<String>,
+        #[serde(skip)]
         pub weighted_avg_classification_string: Vec<String>,
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
         pub per_frame_classifications: BTreeMap<i64, Vec<f32>>,
+        /// SHA-256 of the npy file the frames were computed from, reported through `Provenance`.
+        #[serde(skip)]
+        pub input_sha256: Option<String>,
+        /// Frame-averaged penultimate-layer activations, when the model exposes them.
+        #[serde(skip)]
+        pub embedding: Option<Vec<f32>>,
     }
 
 
     impl FeatureClassificationResult {
 
         pub fn new(
-            instantiated_models: &mut HashMap<Feature, CModule>,
+            models: &ModelRegistry,
             feature_type: &Feature,
             song_id: String,
-        ) -> FeatureClassificationResult {
+        ) -> Result<FeatureClassificationResult, Box<dyn Error>> {
+            Self::from_features_path(models, feature_type, &find_features_path(&song_id))
+        }
 
+        /// Classifies the feature's npy file inside a single track's features directory.
+        pub fn from_features_path(
+            models: &ModelRegistry,
+            feature_type: &Feature,
+            features_path: &Path,
+        ) -> Result<FeatureClassificationResult, Box<dyn Error>> {
+            let entry = models.manifest().entry(feature_type);
 
-            let weight: f32 = match &feature_type {
-                Feature::Ft => 0.76, // 75% 78% 86% 71% 93%
-                Feature::Spectrogram => 0.75,
-                Feature::MelSpectrogram => 0.80,
-                Feature::PowerSpectrogram => 0.77,
-                Feature::Mfcc => 0.69,
-                Feature::ChromaStft => 0.45,
-                Feature::ChromaCqt => 0.49,
-                Feature::ChromaCens => 0.47,
-                Feature::Tonnetz => 0.42,
-            };
+            let (frames, input_sha256) = load_signal_with_hash(
+                &find_signal_path_in(features_path, feature_type),
+                feature_type,
+                &entry.input_shape,
+            )?;
 
-            let feature_tensor =
-                load_and_transform_signal(&feature_type, song_id).expect("Should get tensor");
+            Self::from_input(models, feature_type, &frames, input_sha256)
+        }
 
+        /// Classifies `(frames, input_len)` features that were computed in memory, e.g. by `features::FeatureExtractor`.
+        pub fn from_input(
+            models: &ModelRegistry,
+            feature_type: &Feature,
+            frames: &Array2<f32>,
+            input_sha256: String,
+        ) -> Result<FeatureClassificationResult, Box<dyn Error>> {
+            let entry = models.manifest().entry(feature_type);
 
-            let model = instantiated_models
-                .get_mut(&feature_type)
-                .expect("Should be valid Model");
+            let weight: f32 = entry.weight;
 
+            let logits = models.forward(&feature_type, frames)?;
 
+            let classification = softmax(calibrate(logits, &entry.calibration));
 
-            model.set_eval();
+            let label_order = entry.label_order(&models.manifest().class_labels);
 
+            let mut per_frame_classification: BTreeMap<i64, Vec<f32>> = BTreeMap::new();
 
+            for (i, row) in classification.outer_iter().enumerate() {
+                let row: Vec<f32> = label_order.iter().map(|idx| row[*idx]).collect();
+                per_frame_classification.insert(i as i64, row);
+            }
 
+            let embedding = match models.embed(feature_type, frames) {
+                Some(embeddings) => embeddings?.mean_axis(Axis(0)).map(|mean| mean.to_vec()),
+                None => None,
+            };
 
-            let classification = model
-                .forward_t(&feature_tensor, false)
-                .softmax(-1, Kind::Float);
+            let mut result = Self::from_frames(feature_type.clone(), weight, per_frame_classification);
+            result.input_sha256 = Some(input_sha256);
+            result.embedding = embedding;
 
-            let mut per_frame_classification: BTreeMap<i64, Vec<f32>> = BTreeMap::new();
+            Ok(result)
+        }
 
-            for i in 0..classification.size()[0] {
-                let row = Vec::<f32>::try_from(classification.get(i)).expect("Wrong tensor?");
-                per_frame_classification.insert(i, row);
-            }
+        /// Derives the averaged and weighted probabilities from per-frame probabilities.
+        pub fn from_frames(
+            feature_type: Feature,
+            weight: f32,
+            per_frame_classification: BTreeMap<i64, Vec<f32>>,
+        ) -> FeatureClassificationResult {
+            let class_count = per_frame_classification
+                .values()
+                .next()
+                .map(|row| row.len())
+                .unwrap_or(0);
 
-            let mut avg_classification: [f32; 5] = [0.0; 5];
+            let mut avg_classification: Vec<f32> = vec![0.0; class_count];
 
 
             for frame in per_frame_classification.keys() {
@@ -417,13 +787,15 @@ pub mod ml {
             
 
             Self {
-                feature: feature_type.clone(),
+                feature: feature_type,
                 avg_classification: avg_classification,
                 feature_weight: weight,
                 per_frame_classifications: per_frame_classification,
                 weighted_avg_classification: weighted_avg_classification,
                 weighted_avg_classification_string,
-                avg_classification_string
+                avg_classification_string,
+                input_sha256: None,
+                embedding: None,
             }
         }
     }
@@ -433,27 +805,33 @@ pub mod ml {
 
     /// helper functions
 
-    pub fn get_cmodule_path(feature_type: &Feature) -> &'static Path {
-        match feature_type {
-            Feature::ChromaCens => std::path::Path::new("util/chroma_cens.pt"),
-            Feature::ChromaCqt => std::path::Path::new("util/chroma_cqt.pt"),
-            Feature::ChromaStft => std::path::Path::new("util/chroma_stft.pt"),
-            Feature::Ft => std::path::Path::new("util/ft_model.pt"),
-            Feature::Spectrogram => std::path::Path::new("util/mel_spectrogram_model.pt"),
-            Feature::MelSpectrogram => std::path::Path::new("util/mel_spectrogram_model.pt"),
-            Feature::PowerSpectrogram => std::path::Path::new("util/power_spectrogram_model.pt"),
-            Feature::Mfcc => std::path::Path::new("util/mfcc.pt"),
-            Feature::Tonnetz => std::path::Path::new("util/tonnetz.pt"),
+    /// The 30 s excerpt written by the ETL, always stored as wav.
+    pub fn sample_file_name(song_id: &str) -> String {
+        match song_id.strip_suffix(".mp3") {
+            Some(stem) => format!("{}.wav", stem),
+            None => song_id.to_string(),
         }
     }
 
+    pub fn find_sample_path(song_id: &str) -> PathBuf {
+        std::path::Path::new(&std::env::var("SERVER_DATA").expect("SERVER_DATA should be defined"))
+            .join("30s")
+            .join(sample_file_name(song_id))
+    }
+
     pub fn find_signal_path(feature_type: &Feature, song_id: String) -> PathBuf {
-        let features_path = std::path::Path::new(
-            &std::env::var("SERVER_DATA").expect("SERVER_DATA should be defined"),
-        )
-        .join("features")
-        .join(song_id);
+        find_signal_path_in(&find_features_path(&song_id), feature_type)
+    }
+
+    /// The track's features directory, `SERVER_DATA/features/<song_id>/`.
+    pub fn find_features_path(song_id: &str) -> PathBuf {
+        std::path::Path::new(&std::env::var("SERVER_DATA").expect("SERVER_DATA should be defined"))
+            .join("features")
+            .join(song_id)
+    }
 
+    /// Location of a feature's npy file inside a single track's features directory.
+    pub fn find_signal_path_in(features_path: &Path, feature_type: &Feature) -> PathBuf {
         let final_path = match feature_type {
             Feature::Tonnetz => features_path.join("tonnetz/tonnetz.npy"),
             Feature::ChromaCens => features_path.join("chroma_cens/chroma_cens.npy"),
@@ -471,31 +849,314 @@ pub mod ml {
     pub fn load_and_transform_signal(
         feature_type: &Feature,
         song_id: String,
-    ) -> Result<Tensor, Box<dyn Error>> {
-        let path = find_signal_path(feature_type, song_id);
+        input_shape: &[i64],
+    ) -> Result<Array2<f32>, Box<dyn Error>> {
+        load_signal_from(&find_signal_path(feature_type, song_id), feature_type, input_shape)
+    }
+
+    /// Reads a `(frames, a, b)` npy file into a `(frames, a * b)` array.
+    pub fn load_signal_from(
+        path: &Path,
+        feature_type: &Feature,
+        input_shape: &[i64],
+    ) -> Result<Array2<f32>, Box<dyn Error>> {
+        load_signal_with_hash(path, feature_type, input_shape).map(|(frames, _)| frames)
+    }
+
+    /// Same as `load_signal_from`, also returning the SHA-256 of the file.
+    pub fn load_signal_with_hash(
+        path: &Path,
+        feature_type: &Feature,
+        input_shape: &[i64],
+    ) -> Result<(Array2<f32>, String), Box<dyn Error>> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| CustomError(format!("{} couldn't be opened: {}", path.display(), e)))?;
+        let data: ArrayBase<OwnedRepr<f32>, ndarray::Dim<[usize; 3]>> = Array3::<f32>::read_npy(bytes.as_slice())
+            .map_err(|e| CustomError(format!("{} couldn't be read: {}", path.display(), e)))?;
+
+        if data.dim().0 == 0 {
+            return Err(Box::new(CustomError(format!("{} has no frames", path.display()))));
+        }
 
-        let reader = File::open(path)?;
-        let data: ArrayBase<OwnedRepr<f32>, ndarray::Dim<[usize; 3]>> =
-            Array3::<f32>::read_npy(reader).expect("Should be able to read to array");
+        let frames = model_input(data, feature_type, input_shape)?;
 
+        Ok((frames, sha256_hex(&bytes)))
+    }
+
+    /// Flattens `(frames, a, b)` features into the `(frames, a * b)` input of a model expecting `[a, b]`.
+    pub fn model_input(
+        data: Array3<f32>,
+        feature_type: &Feature,
+        input_shape: &[i64],
+    ) -> Result<Array2<f32>, Box<dyn Error>> {
         let shape = data.dim();
-        let flattened = data.into_raw_vec_and_offset();
-        let tensor = Tensor::try_from(&flattened.0)?
-            .reshape(&[shape.0 as i64, shape.1 as i64 * shape.2 as i64]);
 
-        Ok(tensor)
+        if [shape.1 as i64, shape.2 as i64] != input_shape {
+            return Err(Box::new(CustomError(format!(
+                "{:?} frames have shape {:?}, model expects {:?}",
+                feature_type,
+                [shape.1, shape.2],
+                input_shape
+            ))));
+        }
+
+        Ok(data.into_shape_with_order((shape.0, shape.1 * shape.2))?)
     }
 
-    pub fn instantiate_models(features: [Feature; 9]) -> HashMap<Feature, CModule> {
-        let mut model_hm: HashMap<Feature, CModule> = HashMap::new();
-        for feature in features {
-            model_hm.insert(
-                feature.clone(),
-                CModule::load(get_cmodule_path(&feature))
-                    .expect("Should be able to load the model"),
-            );
+    /// Applies the manifest's temperature and per-class bias to raw model logits, one frame per row.
+    pub fn calibrate(logits: Array2<f32>, calibration: &Calibration) -> Array2<f32> {
+        let scaled = logits / calibration.temperature;
+
+        match &calibration.bias {
+            Some(bias) => scaled + &Array1::from_vec(bias.clone()),
+            None => scaled,
+        }
+    }
+
+    /// Row-wise softmax, shifted by each row's maximum for stability.
+    pub fn softmax(mut logits: Array2<f32>) -> Array2<f32> {
+        for mut row in logits.outer_iter_mut() {
+            let max = row.fold(f32::NEG_INFINITY, |a, b| a.max(*b));
+            row.mapv_inplace(|v| (v - max).exp());
+            let sum = row.sum();
+            row.mapv_inplace(|v| v / sum);
+        }
+
+        logits
+    }
+
+    pub fn sha256_hex(bytes: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(bytes))
+    }
+
+    /// Models loaded once at startup and shared by every handler through axum state.
+    /// Each backend handles its own locking, so different features can run concurrently.
+    pub struct ModelRegistry {
+        manifest: ModelManifest,
+        models: HashMap<Feature, Box<dyn ModelBackend>>,
+        /// Features whose model couldn't be loaded, with the reason.
+        unavailable: HashMap<Feature, String>,
+        /// The file each model was loaded from.
+        model_files: HashMap<Feature, ModelFile>,
+        /// Listed once at load time, so describing the models doesn't wait for a running inference.
+        parameters: HashMap<Feature, Vec<ModelParameter>>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct ModelFile {
+        pub sha256: String,
+        pub size_bytes: u64,
+    }
+
+    impl ModelFile {
+        pub fn sha256_short(&self) -> &str {
+            self.sha256.get(..12).unwrap_or(&self.sha256)
+        }
+
+        pub fn size_mib(&self) -> f64 {
+            self.size_bytes as f64 / (1024.0 * 1024.0)
+        }
+    }
+
+    /// Everything known about one feature's model, for the models page.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct ModelDescription {
+        pub feature: Feature,
+        pub name: String,
+        pub path: PathBuf,
+        /// `None` for models that weren't loaded from a file.
+        pub file: Option<ModelFile>,
+        pub backend: Option<&'static str>,
+        /// Why the model isn't loaded.
+        pub unavailable: Option<String>,
+        pub input_shape: Vec<i64>,
+        /// `None` when the backend can't list the model's parameters.
+        pub parameter_count: Option<usize>,
+        /// For a dense first layer, its last dimension is the flattened input length.
+        pub first_parameter: Option<ModelParameter>,
+        pub weight: f32,
+        /// Share of the summed weights of all models.
+        pub relative_weight: f32,
+        pub validation_accuracy: Option<f32>,
+        pub calibration: Calibration,
+        pub training_data: Option<String>,
+    }
+
+    impl ModelRegistry {
+        pub fn new(manifest: ModelManifest, instantiated_models: HashMap<Feature, Box<dyn ModelBackend>>) -> Self {
+            let unavailable = Feature::all()
+                .into_iter()
+                .filter(|feature| !instantiated_models.contains_key(feature))
+                .map(|feature| (feature, "Model not loaded".to_string()))
+                .collect();
+
+            let parameters = instantiated_models
+                .iter()
+                .filter_map(|(feature, model)| Some((feature.clone(), model.parameters()?)))
+                .collect();
+
+            Self {
+                manifest,
+                models: instantiated_models,
+                unavailable,
+                model_files: HashMap::new(),
+                parameters,
+            }
+        }
+
+        /// Loads every model declared in the manifest and checks that each one
+        /// accepts its declared input shape and returns one score per class label.
+        /// Models failing either step are left out; only fails when none is left.
+        pub fn load(manifest: ModelManifest) -> Result<Self, ManifestError> {
+            let mut registry = Self::new(manifest, HashMap::new());
+
+            for feature in Feature::all() {
+                match registry.load_model(&feature) {
+                    Ok(()) => {
+                        registry.unavailable.remove(&feature);
+                    }
+                    Err(e) => {
+                        tracing::warn!("{:?} model unavailable: {}", feature, e);
+                        registry.unavailable.insert(feature, e.to_string());
+                    }
+                }
+            }
+
+            if registry.models.is_empty() {
+                return Err(ManifestError::Invalid("None of the models could be loaded".to_string()));
+            }
+
+            Ok(registry)
+        }
+
+        fn load_model(&mut self, feature_type: &Feature) -> Result<(), ManifestError> {
+            let entry = self.manifest.entry(feature_type);
+            let path = &entry.path;
+
+            let bytes = std::fs::read(path).map_err(|e| {
+                ManifestError::Invalid(format!("{:?} model {} couldn't be read: {}", feature_type, path.display(), e))
+            })?;
+            let file = ModelFile {
+                sha256: sha256_hex(&bytes),
+                size_bytes: bytes.len() as u64,
+            };
+
+            // loaded from the bytes that were hashed, so a file replaced meanwhile can't slip in
+            let model = backend::load(path, &bytes, &entry.input_shape).map_err(|e| {
+                ManifestError::Invalid(format!("{:?} model {} couldn't be loaded: {}", feature_type, path.display(), e))
+            })?;
+            tracing::info!("{:?} model loaded with the {} backend", feature_type, model.name());
+
+            self.models.insert(feature_type.clone(), model);
+
+            if let Err(e) = self.validate_model(feature_type) {
+                self.models.remove(feature_type);
+                return Err(e);
+            }
+
+            if let Some(parameters) = self.models[feature_type].parameters() {
+                self.parameters.insert(feature_type.clone(), parameters);
+            }
+            self.model_files.insert(feature_type.clone(), file);
+
+            Ok(())
+        }
+
+        pub fn manifest(&self) -> &ModelManifest {
+            &self.manifest
+        }
+
+        /// Features with a loaded model, in `Feature::all()` order.
+        pub fn available_features(&self) -> Vec<Feature> {
+            Feature::all()
+                .into_iter()
+                .filter(|feature| self.models.contains_key(feature))
+                .collect()
+        }
+
+        pub fn unavailable(&self) -> &HashMap<Feature, String> {
+            &self.unavailable
+        }
+
+        pub fn model_sha256(&self, feature_type: &Feature) -> Option<&str> {
+            self.model_files.get(feature_type).map(|file| file.sha256.as_str())
+        }
+
+        /// Every feature's model in `Feature::all()` order, loaded or not.
+        pub fn describe(&self) -> Vec<ModelDescription> {
+            let total_weight: f32 = Feature::all().iter().map(|f| self.manifest.entry(f).weight).sum();
+
+            Feature::all()
+                .into_iter()
+                .map(|feature| {
+                    let entry = self.manifest.entry(&feature);
+                    let parameters = self.parameters.get(&feature);
+
+                    ModelDescription {
+                        name: FeatureDetail::get_name(&feature),
+                        path: entry.path.clone(),
+                        file: self.model_files.get(&feature).cloned(),
+                        backend: self.backend_name(&feature),
+                        unavailable: self.unavailable.get(&feature).cloned(),
+                        input_shape: entry.input_shape.clone(),
+                        parameter_count: parameters.map(|p| p.iter().map(|p| p.numel()).sum()),
+                        first_parameter: parameters.and_then(|p| p.first().cloned()),
+                        weight: entry.weight,
+                        // the manifest only takes positive weights, but NaN couldn't be serialized
+                        relative_weight: if total_weight > 0.0 { entry.weight / total_weight } else { 0.0 },
+                        validation_accuracy: entry.validation_accuracy,
+                        calibration: entry.calibration.clone(),
+                        training_data: self.manifest.training_data(&feature).map(|t| t.to_string()),
+                        feature,
+                    }
+                })
+                .collect()
+        }
+
+        /// Name of the backend running the feature's model.
+        pub fn backend_name(&self, feature_type: &Feature) -> Option<&'static str> {
+            self.models.get(feature_type).map(|model| model.name())
+        }
+
+        fn validate_model(&self, feature_type: &Feature) -> Result<(), ManifestError> {
+            let entry = self.manifest.entry(feature_type);
+            let input_len: i64 = entry.input_shape.iter().product();
+
+            let output = self
+                .forward(feature_type, &Array2::zeros((1, input_len as usize)))
+                .map_err(|e| ManifestError::Invalid(format!("{:?} model failed on its input shape: {}", feature_type, e)))?;
+            let output_len = output.ncols();
+
+            if output_len != entry.class_labels.len() {
+                return Err(ManifestError::Invalid(format!(
+                    "{:?} model returns {} scores but manifest declares {} class labels",
+                    feature_type,
+                    output_len,
+                    entry.class_labels.len()
+                )));
+            }
+
+            Ok(())
+        }
+
+        /// Raw scores of the feature's model, `(frames, classes)` in the model's label order.
+        pub fn forward(&self, feature_type: &Feature, frames: &Array2<f32>) -> Result<Array2<f32>, CustomError> {
+            let model = self.models.get(feature_type).ok_or_else(|| {
+                CustomError(
+                    self.unavailable
+                        .get(feature_type)
+                        .cloned()
+                        .unwrap_or("Model not loaded".to_string()),
+                )
+            })?;
+
+            model.forward(frames)
+        }
+
+        /// Penultimate-layer activations of the feature's model, `None` if it doesn't expose them.
+        pub fn embed(&self, feature_type: &Feature, frames: &Array2<f32>) -> Option<Result<Array2<f32>, CustomError>> {
+            self.models.get(feature_type)?.embed(frames)
         }
-        model_hm
     }
 
 
@@ -503,22 +1164,26 @@ pub mod ml {
     mod tests {
         use std::collections::BTreeMap;
 
-        use tch::kind;
-
         use super::*;
-        
+
+        /// The song the classification tests were written against. They need the models and
+        /// the song's ETL output in `TEST_SERVER_DATA`: `cargo test -- --ignored`.
+        const TEST_SONG: &str = "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3";
+
+        fn test_features_path() -> PathBuf {
+            let server_data = std::env::var("TEST_SERVER_DATA").expect("TEST_SERVER_DATA should point at a SERVER_DATA with the test song");
+            Path::new(&server_data).join("features").join(TEST_SONG)
+        }
 
         #[test] // TODO
+        #[ignore = "needs the model files and TEST_SERVER_DATA"]
         fn classification_works_for_song() {
             
-            unsafe {
-                std::env::set_var("SERVER_DATA", "/home/rwd/dev/test_data");
-            }
 
-            let mut models = instantiate_models(Feature::all());
+            let models = ModelRegistry::load(ModelManifest::load("util/models.json").unwrap()).unwrap();
 
-            let song_classification = SongClassificationResult::new(&mut models,
-                 "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3".to_string());
+            let song_classification = SongClassificationResult::from_features_path(&models,
+                 &test_features_path(), TEST_SONG.to_string(), Ensemble::WeightedMean).unwrap();
 
             dbg!(song_classification.cum_classification);
 
@@ -536,13 +1201,11 @@ pub mod ml {
 
 
         #[test]
+        #[ignore = "needs the model files and TEST_SERVER_DATA"]
         fn classification_ft() {
-            unsafe {
-                std::env::set_var("SERVER_DATA", "/home/rwd/dev/test_data");
-            }
-            let mut models = instantiate_models(Feature::all());
+            let models = ModelRegistry::load(ModelManifest::load("util/models.json").unwrap()).unwrap();
             let feature_classification = 
-            FeatureClassificationResult::new(&mut models, &Feature::Ft, "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3".to_string());
+            FeatureClassificationResult::from_features_path(&models, &Feature::Ft, &test_features_path()).unwrap();
 
             dbg!(&feature_classification.avg_classification);
             dbg!(&feature_classification.weighted_avg_classification);
@@ -553,13 +1216,11 @@ pub mod ml {
 
 
         #[test]
+        #[ignore = "needs the model files and TEST_SERVER_DATA"]
         fn classification_mfcc() {
-            unsafe {
-                std::env::set_var("SERVER_DATA", "/home/rwd/dev/test_data");
-            }
-            let mut models = instantiate_models(Feature::all());
+            let models = ModelRegistry::load(ModelManifest::load("util/models.json").unwrap()).unwrap();
             let feature_classification = 
-            FeatureClassificationResult::new(&mut models, &Feature::Mfcc, "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3".to_string());
+            FeatureClassificationResult::from_features_path(&models, &Feature::Mfcc, &test_features_path()).unwrap();
             assert_eq!(feature_classification.avg_classification.len(), 5 as usize);
 
 
@@ -567,52 +1228,44 @@ pub mod ml {
 
 
         #[test]
+        #[ignore = "needs the model files and TEST_SERVER_DATA"]
         fn classification_tonnetz() {
-            unsafe {
-                std::env::set_var("SERVER_DATA", "/home/rwd/dev/test_data");
-            }
-            let mut models = instantiate_models(Feature::all());
+            let models = ModelRegistry::load(ModelManifest::load("util/models.json").unwrap()).unwrap();
             let feature_classification = 
-            FeatureClassificationResult::new(&mut models, &Feature::Tonnetz, "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3".to_string());
+            FeatureClassificationResult::from_features_path(&models, &Feature::Tonnetz, &test_features_path()).unwrap();
 
             assert_eq!(feature_classification.avg_classification.len(), 5 as usize);
 
         } 
 
         #[test] 
+        #[ignore = "needs the model files and TEST_SERVER_DATA"]
         fn classification_chroma_cens_spectrogram() {
-            unsafe {
-                std::env::set_var("SERVER_DATA", "/home/rwd/dev/test_data");
-            }
-            let mut models = instantiate_models(Feature::all());
+            let models = ModelRegistry::load(ModelManifest::load("util/models.json").unwrap()).unwrap();
             let feature_classification = 
-            FeatureClassificationResult::new(&mut models, &Feature::ChromaCens, "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3".to_string());
+            FeatureClassificationResult::from_features_path(&models, &Feature::ChromaCens, &test_features_path()).unwrap();
 
             assert_eq!(feature_classification.avg_classification.len(), 5 as usize);
 
         }
 
         #[test] 
+        #[ignore = "needs the model files and TEST_SERVER_DATA"]
         fn classification_chroma_stft_spectrogram() {
-            unsafe {
-                std::env::set_var("SERVER_DATA", "/home/rwd/dev/test_data");
-            }
-            let mut models = instantiate_models(Feature::all());
+            let models = ModelRegistry::load(ModelManifest::load("util/models.json").unwrap()).unwrap();
             let feature_classification = 
-            FeatureClassificationResult::new(&mut models, &Feature::ChromaStft, "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3".to_string());
+            FeatureClassificationResult::from_features_path(&models, &Feature::ChromaStft, &test_features_path()).unwrap();
 
             assert_eq!(feature_classification.avg_classification.len(), 5 as usize);
 
         }
 
         #[test] 
+        #[ignore = "needs the model files and TEST_SERVER_DATA"]
         fn classification_chroma_cqt_spectrogram() {
-            unsafe {
-                std::env::set_var("SERVER_DATA", "/home/rwd/dev/test_data");
-            }
-            let mut models = instantiate_models(Feature::all());
+            let models = ModelRegistry::load(ModelManifest::load("util/models.json").unwrap()).unwrap();
             let feature_classification = 
-            FeatureClassificationResult::new(&mut models, &Feature::ChromaCqt, "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3".to_string());
+            FeatureClassificationResult::from_features_path(&models, &Feature::ChromaCqt, &test_features_path()).unwrap();
 
             assert_eq!(feature_classification.avg_classification.len(), 5 as usize);
 
@@ -621,39 +1274,33 @@ pub mod ml {
 
 
         #[test] // works
+        #[ignore = "needs the model files and TEST_SERVER_DATA"]
         fn classification_spectrogram() {
-            unsafe {
-                std::env::set_var("SERVER_DATA", "/home/rwd/dev/test_data");
-            }
-            let mut models = instantiate_models(Feature::all());
+            let models = ModelRegistry::load(ModelManifest::load("util/models.json").unwrap()).unwrap();
             let feature_classification = 
-            FeatureClassificationResult::new(&mut models, &Feature::Spectrogram, "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3".to_string());
+            FeatureClassificationResult::from_features_path(&models, &Feature::Spectrogram, &test_features_path()).unwrap();
 
             assert_eq!(feature_classification.avg_classification.len(), 5 as usize);
 
         } 
 
         #[test] // works
+        #[ignore = "needs the model files and TEST_SERVER_DATA"]
         fn classification_mel_spectrogram() {
-            unsafe {
-                std::env::set_var("SERVER_DATA", "/home/rwd/dev/test_data");
-            }
-            let mut models = instantiate_models(Feature::all());
+            let models = ModelRegistry::load(ModelManifest::load("util/models.json").unwrap()).unwrap();
             let feature_classification = 
-            FeatureClassificationResult::new(&mut models, &Feature::MelSpectrogram, "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3".to_string());
+            FeatureClassificationResult::from_features_path(&models, &Feature::MelSpectrogram, &test_features_path()).unwrap();
 
             assert_eq!(feature_classification.avg_classification.len(), 5 as usize);
 
         } 
 
         #[test] // works
+        #[ignore = "needs the model files and TEST_SERVER_DATA"]
         fn classification_power_spectrogram() {
-            unsafe {
-                std::env::set_var("SERVER_DATA", "/home/rwd/dev/test_data");
-            }
-            let mut models = instantiate_models(Feature::all());
+            let models = ModelRegistry::load(ModelManifest::load("util/models.json").unwrap()).unwrap();
             let feature_classification = 
-            FeatureClassificationResult::new(&mut models, &Feature::PowerSpectrogram, "8d298e5b-e11a-4ab4-ab38-7149c710a90a-faintofficialmusicvideo[4kupgrade]–linkinpark.mp3".to_string());
+            FeatureClassificationResult::from_features_path(&models, &Feature::PowerSpectrogram, &test_features_path()).unwrap();
 
             assert_eq!(feature_classification.avg_classification.len(), 5 as usize);
 
@@ -661,19 +1308,2731 @@ pub mod ml {
 
 
         #[test]
-        fn should_load_model() {
-            let model = CModule::load(get_cmodule_path(&Feature::Ft))
-                .expect("Should be able to load the model");
+        fn major_class_follows_manifest_labels() {
+            let labels: Vec<String> = ["blues", "classical", "country", "disco", "hiphop", "jazz", "metal", "pop", "reggae", "rock"]
+                .iter()
+                .map(|l| l.to_string())
+                .collect();
+            let mut cum_classification = vec![0.05; 10];
+            cum_classification[6] = 0.55;
 
+            let major_class = SongClassificationResult::get_major_class(&cum_classification, &labels).unwrap();
 
-            assert!(true)
+            assert_eq!(major_class, Class { index: 6, label: "metal".to_string() });
         }
 
         #[test]
-        fn gets_model_path() {
-            let path = get_cmodule_path(&Feature::ChromaCens);
+        fn from_frames_averages_and_weights() {
+            let per_frame: BTreeMap<i64, Vec<f32>> = BTreeMap::from([
+                (0, vec![0.2, 0.8]),
+                (1, vec![0.6, 0.4]),
+            ]);
 
-            assert_eq!(std::path::Path::new("util/chroma_cens.pt"), path)
+            let result = FeatureClassificationResult::from_frames(Feature::Mfcc, 0.5, per_frame);
+
+            assert_eq!(result.avg_classification, vec![0.4, 0.6]);
+            assert_eq!(result.weighted_avg_classification, vec![0.2, 0.3]);
+            assert_eq!(result.avg_classification_string, vec!["40.00%", "60.00%"]);
+        }
+
+        #[test]
+        fn calibrated_softmax_sums_to_one_per_frame() {
+            let logits = array![[2.0, 0.0], [0.0, 0.0]];
+            let calibration = Calibration { temperature: 2.0, bias: Some(vec![0.0, 1.0]) };
+
+            let probabilities = softmax(calibrate(logits, &calibration));
+
+            // [1, 0] + [0, 1] -> equal, [0, 0] + [0, 1] -> second wins
+            assert!((probabilities[[0, 0]] - 0.5).abs() < 1e-6);
+            assert!(probabilities[[1, 1]] > probabilities[[1, 0]]);
+            for row in probabilities.outer_iter() {
+                assert!((row.sum() - 1.0).abs() < 1e-6);
+            }
+        }
+
+        #[test]
+        fn serialized_result_omits_display_strings_and_cleared_frames() {
+            let mut feature_result = FeatureClassificationResult::from_frames(
+                Feature::ChromaCqt,
+                0.5,
+                BTreeMap::from([(0, vec![0.25, 0.75])]),
+            );
+            feature_result.per_frame_classifications.clear();
+
+            let json = serde_json::to_value(&feature_result).unwrap();
+
+            assert_eq!(json["feature"], "chroma_cqt");
+            assert!(json.get("avg_classification_string").is_none());
+            assert!(json.get("per_frame_classifications").is_none());
+        }
+
+        #[test]
+        fn timeline_weights_frames_and_centres_timestamps() {
+            let rock = FeatureClassificationResult::from_frames(
+                Feature::Ft,
+                3.0,
+                BTreeMap::from([(0, vec![1.0, 0.0]), (1, vec![1.0, 0.0])]),
+            );
+            let pop = FeatureClassificationResult::from_frames(
+                Feature::Mfcc,
+                1.0,
+                BTreeMap::from([(0, vec![0.0, 1.0]), (1, vec![0.0, 1.0])]),
+            );
+            let result = SongClassificationResult::from_features(
+                "song".to_string(),
+                vec!["Rock".to_string(), "Pop".to_string()],
+                vec![rock, pop],
+                Vec::new(),
+                Ensemble::WeightedMean,
+            );
+
+            let timeline = result.get_timeline();
+
+            assert_eq!(timeline.len(), 2);
+            assert_eq!(timeline[0], TimelineFrame { time: 0.5, probabilities: vec![0.75, 0.25] });
+            assert!((timeline[1].time - 0.6).abs() < 1e-6);
+        }
+
+        #[test]
+        fn timeline_follows_the_result_strategy() {
+            let rock = FeatureClassificationResult::from_frames(Feature::Ft, 1.0, BTreeMap::from([(0, vec![0.6, 0.4])]));
+            let pop = FeatureClassificationResult::from_frames(Feature::Mfcc, 1.0, BTreeMap::from([(0, vec![0.1, 0.9])]));
+            let result = SongClassificationResult::from_features(
+                "song".to_string(),
+                vec!["Rock".to_string(), "Pop".to_string()],
+                vec![rock, pop],
+                Vec::new(),
+                Ensemble::WeightedMean,
+            );
+
+            let max_confidence = result.with_strategy(Ensemble::MaxConfidence);
+
+            assert_eq!(max_confidence.get_timeline()[0].probabilities, vec![0.1, 0.9]);
+            assert_eq!(max_confidence.get_timeline()[0].probabilities, max_confidence.cum_classification);
+        }
+
+        #[test]
+        fn song_without_any_feature_reports_every_missing_one() {
+            let manifest = ModelManifest::parse(include_str!("../util/models.json")).unwrap();
+            let models = ModelRegistry::new(manifest, HashMap::new());
+
+            let features_path = std::env::temp_dir().join("missing-features");
+            let missing = SongClassificationResult::from_features_path(&models, &features_path, "missing.mp3".to_string(), Ensemble::WeightedMean)
+                .unwrap_err();
+
+            assert_eq!(missing.len(), 9);
+            assert_eq!(missing[0].feature, Feature::Ft);
+        }
+
+        #[test]
+        fn remaining_features_are_renormalized_and_missing_ones_kept() {
+            let rock = FeatureClassificationResult::from_frames(Feature::Ft, 0.5, BTreeMap::from([(0, vec![1.0, 0.0])]));
+            let pop = FeatureClassificationResult::from_frames(Feature::Mfcc, 0.25, BTreeMap::from([(0, vec![0.0, 1.0])]));
+            let missing = vec![MissingFeature {
+                feature: Feature::Tonnetz,
+                reason: "tonnetz.npy couldn't be opened".to_string(),
+            }];
+
+            let result = SongClassificationResult::from_features(
+                "song".to_string(),
+                vec!["Rock".to_string(), "Pop".to_string()],
+                vec![rock, pop],
+                missing.clone(),
+                Ensemble::WeightedMean,
+            )
+            .with_strategy(Ensemble::MajorityVote);
+
+            assert_eq!(result.cum_classification, vec![2.0 / 3.0, 1.0 / 3.0]);
+            assert_eq!(result.uncertainty.feature_count, 2);
+            assert_eq!(result.missing_features, missing);
+        }
+
+        #[test]
+        fn sample_name_swaps_mp3_for_wav() {
+            assert_eq!(sample_file_name("abc-song.mp3"), "abc-song.wav");
+            assert_eq!(sample_file_name("abc-song.wav"), "abc-song.wav");
+        }
+
+        #[test]
+        fn major_class_takes_first_index_on_ties() {
+            let labels = vec!["Rock".to_string(), "Pop".to_string(), "Jazz".to_string()];
+
+            let major_class = SongClassificationResult::get_major_class(&vec![0.4, 0.4, 0.2], &labels).unwrap();
+
+            assert_eq!(major_class.index, 0);
+        }
+
+        #[test]
+        fn uncertainty_flags_near_ties_and_counts_agreement() {
+            let classifications = vec![
+                FeatureClassificationResult::from_frames(Feature::Ft, 1.0, BTreeMap::from([(0, vec![0.7, 0.3])])),
+                FeatureClassificationResult::from_frames(Feature::Mfcc, 1.0, BTreeMap::from([(0, vec![0.36, 0.64])])),
+            ];
+            let cum = Ensemble::WeightedMean.combine(&classifications);
+            let major_class = Class { index: 0, label: "Rock".to_string() };
+
+            let uncertainty = Uncertainty::new(&cum, &classifications, &major_class, 0.1);
+
+            assert!((uncertainty.margin - 0.06).abs() < 1e-5);
+            assert!(uncertainty.uncertain);
+            assert_eq!(uncertainty.agreeing_features, 1);
+            assert_eq!(uncertainty.disagreement, 0.5);
+            assert!(uncertainty.normalized_entropy > 0.99);
+        }
+
+        #[test]
+        fn genres_rank_secondary_genres_above_threshold() {
+            let labels = vec!["Rock".to_string(), "Pop".to_string(), "Electronic".to_string(), "Jazz".to_string()];
+
+            let genres = Genres::new(&[0.45, 0.1, 0.4, 0.05], &labels, 0.15);
+
+            let ranked: Vec<(&str, GenreRole)> = genres.ranked.iter().map(|g| (g.class.label.as_str(), g.role)).collect();
+            assert_eq!(ranked, vec![("Rock", GenreRole::Primary), ("Electronic", GenreRole::CoPrimary)]);
+            assert_eq!(genres.summary, "Primarily Rock and Electronic");
+
+            let genres = Genres::new(&[0.45, 0.2, 0.3, 0.05], &labels, 0.15);
+
+            assert_eq!(genres.summary, "Primarily Rock, with elements of Electronic and Pop");
+            assert!((genres.ranked[1].relative_confidence - 0.3 / 0.45).abs() < 1e-6);
+        }
+
+        #[test]
+        fn genres_always_list_the_top_genre() {
+            let labels = vec!["Rock".to_string(), "Pop".to_string()];
+
+            let genres = Genres::new(&[0.5, 0.5], &labels, 0.9);
+
+            assert_eq!(genres.ranked.len(), 1);
+            assert_eq!(genres.ranked[0].class.index, 0);
+            assert_eq!(genres.summary, "Primarily Rock");
+        }
+
+        #[test]
+        #[ignore = "needs the model files"]
+        fn should_load_model() {
+            let manifest = ModelManifest::load("util/models.json").unwrap();
+            let entry = manifest.entry(&Feature::Ft);
+            let bytes = std::fs::read(&entry.path).expect("Model file should be readable");
+            let model = backend::load(&entry.path, &bytes, &entry.input_shape)
+                .expect("Should be able to load the model");
+
+
+            assert!(true)
+        }
+
+        #[test]
+        fn gets_model_path() {
+            let manifest = ModelManifest::load("util/models.json").unwrap();
+            let path = &manifest.entry(&Feature::ChromaCens).path;
+
+            assert_eq!(std::path::Path::new("util/chroma_cens.pt"), path)
+        }
+
+        struct DenseModel;
+
+        impl ModelBackend for DenseModel {
+            fn forward(&self, frames: &Array2<f32>) -> Result<Array2<f32>, CustomError> {
+                Ok(Array2::zeros((frames.nrows(), 5)))
+            }
+
+            fn parameters(&self) -> Option<Vec<ModelParameter>> {
+                Some(vec![
+                    ModelParameter { name: "fc.weight".to_string(), shape: vec![5, 264] },
+                    ModelParameter { name: "fc.bias".to_string(), shape: vec![5] },
+                ])
+            }
+
+            fn name(&self) -> &'static str {
+                "test"
+            }
+        }
+
+        #[test]
+        fn models_are_described_with_parameters_and_weights() {
+            let manifest = ModelManifest::parse(include_str!("../util/models.json")).unwrap();
+            let total_weight: f32 = manifest.models.values().map(|entry| entry.weight).sum();
+            let models = ModelRegistry::new(
+                manifest,
+                HashMap::from([(Feature::Tonnetz, Box::new(DenseModel) as Box<dyn ModelBackend>)]),
+            );
+
+            let descriptions = models.describe();
+            assert_eq!(descriptions.len(), 9);
+
+            let tonnetz = descriptions.iter().find(|d| d.feature == Feature::Tonnetz).unwrap();
+            assert_eq!(tonnetz.parameter_count, Some(5 * 264 + 5));
+            assert_eq!(tonnetz.first_parameter.as_ref().unwrap().shape, vec![5, 264]);
+            assert_eq!(tonnetz.input_shape, vec![6, 44]);
+            assert_eq!(tonnetz.backend, Some("test"));
+            assert!(tonnetz.unavailable.is_none());
+            assert!((tonnetz.relative_weight - 0.42 / total_weight).abs() < 1e-6);
+
+            let ft = descriptions.iter().find(|d| d.feature == Feature::Ft).unwrap();
+            assert_eq!(ft.parameter_count, None);
+            assert!(ft.unavailable.is_some());
+        }
+
+        #[test]
+        fn zero_total_weight_is_described_as_zero_share() {
+            let mut manifest = ModelManifest::parse(include_str!("../util/models.json")).unwrap();
+            manifest.models.values_mut().for_each(|entry| entry.weight = 0.0);
+            let models = ModelRegistry::new(manifest, HashMap::new());
+
+            let descriptions = models.describe();
+            assert!(descriptions.iter().all(|d| d.relative_weight == 0.0));
+            assert!(serde_json::to_string(&descriptions).is_ok());
+        }
+    }
+}
+
+pub mod backend {
+    use std::path::Path;
+
+    use ndarray::Array2;
+    use serde::Serialize;
+
+    use crate::ml::ml::CustomError;
+
+    /// A learned tensor of a model.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct ModelParameter {
+        pub name: String,
+        pub shape: Vec<i64>,
+    }
+
+    impl ModelParameter {
+        pub fn numel(&self) -> usize {
+            self.shape.iter().product::<i64>() as usize
+        }
+    }
+
+    /// A loaded model: takes `(frames, input_len)` features and returns `(frames, classes)` raw scores.
+    /// Implementations do their own locking so a registry can share them between workers.
+    pub trait ModelBackend: Send + Sync {
+        fn forward(&self, frames: &Array2<f32>) -> Result<Array2<f32>, CustomError>;
+
+        /// Penultimate-layer activations, `(frames, dims)`. `None` when the model doesn't expose them.
+        fn embed(&self, _frames: &Array2<f32>) -> Option<Result<Array2<f32>, CustomError>> {
+            None
+        }
+
+        /// Learned tensors in declaration order, `None` when the backend can't list them.
+        fn parameters(&self) -> Option<Vec<ModelParameter>> {
+            None
+        }
+
+        fn name(&self) -> &'static str;
+    }
+
+    /// Loads the model from the contents of the file at `path`. The backend is picked from the
+    /// file extension: `.onnx` runs on tract, anything else is treated as TorchScript.
+    pub fn load(path: &Path, bytes: &[u8], input_shape: &[i64]) -> Result<Box<dyn ModelBackend>, CustomError> {
+        let input_len = input_shape.iter().product::<i64>() as usize;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("onnx") => load_onnx(path, bytes, input_len),
+            _ => load_torch(path, bytes),
+        }
+    }
+
+    #[cfg(feature = "onnx")]
+    fn load_onnx(_path: &Path, bytes: &[u8], input_len: usize) -> Result<Box<dyn ModelBackend>, CustomError> {
+        Ok(Box::new(onnx::OnnxModel::load(bytes, input_len)?))
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    fn load_onnx(path: &Path, _bytes: &[u8], _input_len: usize) -> Result<Box<dyn ModelBackend>, CustomError> {
+        Err(CustomError(format!("{} needs the `onnx` feature", path.display())))
+    }
+
+    #[cfg(feature = "tch")]
+    fn load_torch(_path: &Path, bytes: &[u8]) -> Result<Box<dyn ModelBackend>, CustomError> {
+        Ok(Box::new(torch::TorchModel::load(bytes)?))
+    }
+
+    #[cfg(not(feature = "tch"))]
+    fn load_torch(path: &Path, _bytes: &[u8]) -> Result<Box<dyn ModelBackend>, CustomError> {
+        Err(CustomError(format!("{} needs the `tch` feature", path.display())))
+    }
+
+    #[cfg(feature = "tch")]
+    pub mod torch {
+        use std::{
+            io::Cursor,
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Mutex,
+            },
+        };
+
+        use ndarray::Array2;
+        use tch::{CModule, Tensor};
+
+        use super::{ModelBackend, ModelParameter};
+        use crate::ml::ml::CustomError;
+
+        /// Method a TorchScript model can export (`@torch.jit.export`) to return its
+        /// penultimate-layer activations.
+        pub const EMBED_METHOD: &str = "embed";
+
+        /// TorchScript model run through libtorch.
+        pub struct TorchModel {
+            model: Mutex<CModule>,
+            /// Set once calling `EMBED_METHOD` failed, so it isn't tried again.
+            no_embed: AtomicBool,
+        }
+
+        impl TorchModel {
+            pub fn load(bytes: &[u8]) -> Result<Self, CustomError> {
+                let mut model = CModule::load_data(&mut Cursor::new(bytes)).map_err(|e| CustomError(e.to_string()))?;
+                model.set_eval();
+
+                Ok(Self {
+                    model: Mutex::new(model),
+                    no_embed: AtomicBool::new(false),
+                })
+            }
+
+            /// Runs `forward`, or the named method, on `(frames, input_len)` and returns `(frames, outputs)`.
+            fn run(&self, frames: &Array2<f32>, method: Option<&str>) -> Result<Array2<f32>, CustomError> {
+                let (rows, cols) = frames.dim();
+                let input = Tensor::from_slice(&frames.iter().copied().collect::<Vec<f32>>())
+                    .reshape([rows as i64, cols as i64]);
+
+                let model = self.model.lock().expect("Model lock should not be poisoned");
+                let output = tch::no_grad(|| match method {
+                    Some(method) => model.method_ts(method, &[input]),
+                    None => model.forward_ts(&[input]),
+                })
+                .map_err(|e| CustomError(e.to_string()))?;
+
+                let outputs = *output.size().last().unwrap_or(&0) as usize;
+                let values = Vec::<f32>::try_from(output.flatten(0, -1)).map_err(|e| CustomError(e.to_string()))?;
+
+                Array2::from_shape_vec((rows, outputs), values).map_err(|e| CustomError(e.to_string()))
+            }
+        }
+
+        impl ModelBackend for TorchModel {
+            fn forward(&self, frames: &Array2<f32>) -> Result<Array2<f32>, CustomError> {
+                self.run(frames, None)
+            }
+
+            fn embed(&self, frames: &Array2<f32>) -> Option<Result<Array2<f32>, CustomError>> {
+                if self.no_embed.load(Ordering::Relaxed) {
+                    return None;
+                }
+
+                match self.run(frames, Some(EMBED_METHOD)) {
+                    Ok(embeddings) => Some(Ok(embeddings)),
+                    Err(e) => {
+                        tracing::debug!("No `{}` method, using probabilities instead: {}", EMBED_METHOD, e);
+                        self.no_embed.store(true, Ordering::Relaxed);
+                        None
+                    }
+                }
+            }
+
+            fn parameters(&self) -> Option<Vec<ModelParameter>> {
+                let model = self.model.lock().expect("Model lock should not be poisoned");
+
+                match model.named_parameters() {
+                    Ok(parameters) => Some(
+                        parameters
+                            .into_iter()
+                            .map(|(name, tensor)| ModelParameter { name, shape: tensor.size() })
+                            .collect(),
+                    ),
+                    Err(e) => {
+                        tracing::debug!("Parameters couldn't be listed: {}", e);
+                        None
+                    }
+                }
+            }
+
+            fn name(&self) -> &'static str {
+                "torchscript"
+            }
+        }
+    }
+
+    #[cfg(feature = "onnx")]
+    pub mod onnx {
+        use std::io::Cursor;
+
+        use ndarray::Array2;
+        use tract_onnx::prelude::*;
+        use tract_onnx::tract_core::ops::konst::Const;
+
+        use super::{ModelBackend, ModelParameter};
+        use crate::ml::ml::CustomError;
+
+        /// ONNX model run through tract. The first input axis is left symbolic so
+        /// any number of frames can go through one plan.
+        pub struct OnnxModel {
+            plan: TypedRunnableModel<TypedModel>,
+            /// Float initializers, listed before optimization folds them into other ops.
+            parameters: Vec<ModelParameter>,
+        }
+
+        impl OnnxModel {
+            pub fn load(bytes: &[u8], input_len: usize) -> Result<Self, CustomError> {
+                let (plan, parameters) = Self::plan(bytes, input_len).map_err(|e| CustomError(e.to_string()))?;
+
+                Ok(Self { plan, parameters })
+            }
+
+            fn plan(bytes: &[u8], input_len: usize) -> TractResult<(TypedRunnableModel<TypedModel>, Vec<ModelParameter>)> {
+                let mut model = tract_onnx::onnx().model_for_read(&mut Cursor::new(bytes))?;
+                let frames = model.symbol_table.sym("frames");
+                model.set_input_fact(
+                    0,
+                    InferenceFact::dt_shape(f32::datum_type(), tvec!(frames.to_dim(), input_len.to_dim())),
+                )?;
+                // the exported output shape may name the batch axis differently, let tract infer it
+                model.set_output_fact(0, InferenceFact::default())?;
+
+                let model = model.into_typed()?;
+                let parameters = model
+                    .nodes()
+                    .iter()
+                    .filter_map(|node| {
+                        let konst = node.op_as::<Const>()?;
+                        konst.0.datum_type().is_float().then(|| ModelParameter {
+                            name: node.name.clone(),
+                            shape: konst.0.shape().iter().map(|d| *d as i64).collect(),
+                        })
+                    })
+                    .collect();
+
+                Ok((model.into_optimized()?.into_runnable()?, parameters))
+            }
+        }
+
+        impl ModelBackend for OnnxModel {
+            fn forward(&self, frames: &Array2<f32>) -> Result<Array2<f32>, CustomError> {
+                let (rows, cols) = frames.dim();
+                let values: Vec<f32> = frames.iter().copied().collect();
+                let input = tract_onnx::prelude::Tensor::from_shape(&[rows, cols], &values)
+                    .map_err(|e| CustomError(e.to_string()))?;
+
+                let outputs = self.plan.run(tvec!(input.into())).map_err(|e| CustomError(e.to_string()))?;
+                let output = outputs[0].to_array_view::<f32>().map_err(|e| CustomError(e.to_string()))?;
+
+                let classes = *output.shape().last().unwrap_or(&0);
+                Array2::from_shape_vec((rows, classes), output.iter().copied().collect())
+                    .map_err(|e| CustomError(e.to_string()))
+            }
+
+            fn parameters(&self) -> Option<Vec<ModelParameter>> {
+                Some(self.parameters.clone())
+            }
+
+            fn name(&self) -> &'static str {
+                "onnx"
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Single MatMul from 8 inputs to 3 outputs with a constant weight.
+        const MATMUL_ONNX: &[u8] = include_bytes!("../util/fixtures/matmul.onnx");
+
+        #[test]
+        #[cfg(feature = "onnx")]
+        fn onnx_extension_dispatches_to_the_onnx_backend() {
+            let model = load(Path::new("util/fixtures/matmul.onnx"), MATMUL_ONNX, &[1, 8])
+                .expect("Should be able to load the ONNX model");
+
+            assert_eq!(model.name(), "onnx");
+            assert_eq!(model.parameters().map(|p| p.len()), Some(1));
+        }
+
+        #[test]
+        #[cfg(not(feature = "onnx"))]
+        fn onnx_extension_dispatches_to_the_onnx_backend() {
+            let Err(e) = load(Path::new("util/fixtures/matmul.onnx"), MATMUL_ONNX, &[1, 8]) else {
+                panic!("An ONNX model shouldn't load without the `onnx` feature");
+            };
+
+            assert_eq!(e.0, "util/fixtures/matmul.onnx needs the `onnx` feature");
+        }
+    }
+}
+
+pub mod ensemble {
+
+    use std::{env, fmt, str::FromStr};
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::ml::ml::FeatureClassificationResult;
+
+    /// How the per-feature distributions are combined into the song-level one.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Ensemble {
+        /// Weighted arithmetic mean of the per-feature averages.
+        WeightedMean,
+        /// Weighted mean of log-probabilities (log pooling), renormalized.
+        GeometricMean,
+        /// Every feature votes for its argmax with its weight; result is the vote share.
+        MajorityVote,
+        /// Distribution of the single most confident feature model.
+        MaxConfidence,
+        /// Every frame votes for its argmax; the per-feature vote shares are then weighted-averaged.
+        FramePooling,
+    }
+
+    impl Ensemble {
+        pub fn all() -> [Ensemble; 5] {
+            [
+                Ensemble::WeightedMean,
+                Ensemble::GeometricMean,
+                Ensemble::MajorityVote,
+                Ensemble::MaxConfidence,
+                Ensemble::FramePooling,
+            ]
+        }
+
+        pub fn key(&self) -> &'static str {
+            match self {
+                Ensemble::WeightedMean => "weighted_mean",
+                Ensemble::GeometricMean => "geometric_mean",
+                Ensemble::MajorityVote => "majority_vote",
+                Ensemble::MaxConfidence => "max_confidence",
+                Ensemble::FramePooling => "frame_pooling",
+            }
+        }
+
+        /// Default strategy taken from `ENSEMBLE_STRATEGY`, weighted mean when unset.
+        /// Fails on an unknown strategy instead of silently using the default.
+        pub fn from_env() -> Result<Self, String> {
+            match env::var("ENSEMBLE_STRATEGY") {
+                Ok(s) => s.parse().map_err(|e| format!("ENSEMBLE_STRATEGY: {}", e)),
+                Err(_) => Ok(Ensemble::WeightedMean),
+            }
+        }
+
+        pub fn combine(&self, classifications: &[FeatureClassificationResult]) -> Vec<f32> {
+            let class_count = classifications
+                .first()
+                .map(|c| c.avg_classification.len())
+                .unwrap_or(0);
+
+            let total_weight: f32 = classifications.iter().map(|c| c.feature_weight).sum();
+
+            match self {
+                Ensemble::FramePooling => {
+                    let mut base = vec![0.0; class_count];
+                    for classification in classifications {
+                        let frames = &classification.per_frame_classifications;
+                        if frames.is_empty() {
+                            continue;
+                        }
+                        for row in frames.values() {
+                            base[argmax(row)] += classification.feature_weight / frames.len() as f32;
+                        }
+                    }
+                    base.iter().map(|val| val / total_weight).collect()
+                }
+                _ => {
+                    let rows: Vec<(&[f32], f32)> = classifications
+                        .iter()
+                        .map(|c| (c.avg_classification.as_slice(), c.feature_weight))
+                        .collect();
+                    self.combine_rows(&rows)
+                }
+            }
+        }
+
+        /// Combines one probability row per feature with its weight, e.g. the features' averages
+        /// or a single frame of every feature. Pooling the votes of one frame is a majority vote.
+        pub fn combine_rows(&self, rows: &[(&[f32], f32)]) -> Vec<f32> {
+            let class_count = rows.first().map(|(row, _)| row.len()).unwrap_or(0);
+
+            let total_weight: f32 = rows.iter().map(|(_, weight)| weight).sum();
+
+            match self {
+                Ensemble::WeightedMean => {
+                    let mut base = vec![0.0; class_count];
+                    for (row, weight) in rows {
+                        row.iter().enumerate().for_each(|(idx, p)| base[idx] += p * weight);
+                    }
+                    base.iter().map(|val| val / total_weight).collect()
+                }
+                Ensemble::GeometricMean => {
+                    let mut log_base = vec![0.0; class_count];
+                    for (row, weight) in rows {
+                        row.iter()
+                            .enumerate()
+                            .for_each(|(idx, p)| log_base[idx] += p.max(f32::EPSILON).ln() * weight);
+                    }
+                    normalize(log_base.iter().map(|val| (val / total_weight).exp()).collect())
+                }
+                Ensemble::MajorityVote | Ensemble::FramePooling => {
+                    let mut votes = vec![0.0; class_count];
+                    for (row, weight) in rows {
+                        votes[argmax(row)] += weight;
+                    }
+                    votes.iter().map(|val| val / total_weight).collect()
+                }
+                Ensemble::MaxConfidence => rows
+                    .iter()
+                    .max_by(|(a, _), (b, _)| a[argmax(a)].total_cmp(&b[argmax(b)]))
+                    .map(|(row, _)| row.to_vec())
+                    .unwrap_or_default(),
+            }
+        }
+    }
+
+    impl fmt::Display for Ensemble {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Ensemble::WeightedMean => write!(f, "Weighted mean"),
+                Ensemble::GeometricMean => write!(f, "Geometric mean"),
+                Ensemble::MajorityVote => write!(f, "Majority vote"),
+                Ensemble::MaxConfidence => write!(f, "Max confidence"),
+                Ensemble::FramePooling => write!(f, "Frame pooling"),
+            }
+        }
+    }
+
+    impl FromStr for Ensemble {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Ensemble::all()
+                .into_iter()
+                .find(|e| e.key() == s)
+                .ok_or(format!(
+                    "Unknown ensemble strategy {}, expected one of {}",
+                    s,
+                    Ensemble::all().map(|e| e.key()).join(", ")
+                ))
+        }
+    }
+
+    /// Index of the largest value, the first one on ties.
+    pub fn argmax(values: &[f32]) -> usize {
+        values
+            .iter()
+            .enumerate()
+            .fold(0, |best, (idx, val)| if *val > values[best] { idx } else { best })
+    }
+
+    fn normalize(values: Vec<f32>) -> Vec<f32> {
+        let sum: f32 = values.iter().sum();
+        values.iter().map(|val| val / sum).collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::BTreeMap;
+
+        use super::*;
+        use crate::ml::ml::Feature;
+
+        fn results() -> Vec<FeatureClassificationResult> {
+            vec![
+                FeatureClassificationResult::from_frames(
+                    Feature::Ft,
+                    1.0,
+                    BTreeMap::from([(0, vec![0.9, 0.1]), (1, vec![0.9, 0.1]), (2, vec![0.3, 0.7])]),
+                ),
+                FeatureClassificationResult::from_frames(
+                    Feature::Mfcc,
+                    1.0,
+                    BTreeMap::from([(0, vec![0.4, 0.6]), (1, vec![0.4, 0.6]), (2, vec![0.4, 0.6])]),
+                ),
+                FeatureClassificationResult::from_frames(
+                    Feature::Tonnetz,
+                    2.0,
+                    BTreeMap::from([(0, vec![0.45, 0.55]), (1, vec![0.45, 0.55]), (2, vec![0.45, 0.55])]),
+                ),
+            ]
+        }
+
+        fn assert_close(actual: Vec<f32>, expected: Vec<f32>) {
+            assert_eq!(actual.len(), expected.len());
+            actual
+                .iter()
+                .zip(expected.iter())
+                .for_each(|(a, e)| assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected));
+        }
+
+        #[test]
+        fn weighted_mean() {
+            assert_close(Ensemble::WeightedMean.combine(&results()), vec![0.5, 0.5]);
+        }
+
+        #[test]
+        fn geometric_mean_is_normalized() {
+            let combined = Ensemble::GeometricMean.combine(&results());
+
+            assert!((combined.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+            // the arithmetic mean ties, log pooling penalizes the 0.3 Ft gives to the second class
+            assert!(combined[0] > combined[1]);
+        }
+
+        #[test]
+        fn majority_vote_uses_weights() {
+            assert_close(Ensemble::MajorityVote.combine(&results()), vec![0.25, 0.75]);
+        }
+
+        #[test]
+        fn max_confidence_picks_most_confident_feature() {
+            assert_close(Ensemble::MaxConfidence.combine(&results()), vec![0.7, 0.3]);
+        }
+
+        #[test]
+        fn frame_pooling_counts_frame_votes() {
+            let expected_rock = (2.0 / 3.0) / 4.0;
+            assert_close(
+                Ensemble::FramePooling.combine(&results()),
+                vec![expected_rock, 1.0 - expected_rock],
+            );
+        }
+
+        #[test]
+        fn parses_strategy_keys() {
+            for strategy in Ensemble::all() {
+                assert_eq!(strategy.key().parse::<Ensemble>(), Ok(strategy));
+            }
+            let e = "median".parse::<Ensemble>().unwrap_err();
+            assert!(e.contains("median") && e.contains("weighted_mean") && e.contains("frame_pooling"));
+        }
+    }
+}
+
+#[allow(unused)]
+pub mod manifest {
+
+    use std::{
+        collections::HashMap,
+        env, fmt,
+        path::{Path, PathBuf},
+    };
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::ml::ml::Feature;
+
+    /// Describes the model behind every `Feature`, read from `MODEL_MANIFEST`
+    /// (`util/models.json` by default) so retrained models can be swapped without recompiling.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ModelManifest {
+        pub version: String,
+        /// Order in which class probabilities are reported by every result.
+        pub class_labels: Vec<String>,
+        pub models: HashMap<Feature, ModelEntry>,
+        /// Free-text description of what the models were trained on, shown on the models page.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub training_data: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ModelEntry {
+        pub path: PathBuf,
+        pub weight: f32,
+        /// Shape of a single frame, i.e. the last two dimensions of the feature npy file.
+        pub input_shape: Vec<i64>,
+        /// Labels in the order the model outputs them; may differ from the manifest order.
+        pub class_labels: Vec<String>,
+        /// Measured by `back eval --write`; `None` until it has been.
+        #[serde(default)]
+        pub validation_accuracy: Option<f32>,
+        #[serde(default)]
+        pub calibration: Calibration,
+        /// Overrides the manifest's `training_data` for this model.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub training_data: Option<String>,
+    }
+
+    /// Applied to the logits before the softmax: `logits / temperature + bias`.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Calibration {
+        pub temperature: f32,
+        /// One value per label, in the model's output order.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub bias: Option<Vec<f32>>,
+    }
+
+    impl Default for Calibration {
+        fn default() -> Self {
+            Self {
+                temperature: 1.0,
+                bias: None,
+            }
+        }
+    }
+
+    impl Calibration {
+        pub fn apply(&self, logits: &[f32]) -> Vec<f32> {
+            logits
+                .iter()
+                .enumerate()
+                .map(|(idx, l)| {
+                    l / self.temperature + self.bias.as_ref().map(|b| b[idx]).unwrap_or(0.0)
+                })
+                .collect()
+        }
+    }
+
+    impl ModelEntry {
+        /// For every label in `class_labels`, the index of the matching model output.
+        pub fn label_order(&self, class_labels: &[String]) -> Vec<usize> {
+            class_labels
+                .iter()
+                .map(|label| {
+                    self.class_labels
+                        .iter()
+                        .position(|l| l == label)
+                        .expect("Manifest is validated to contain every label")
+                })
+                .collect()
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum ManifestError {
+        Io(String),
+        Parse(String),
+        Invalid(String),
+    }
+
+    impl fmt::Display for ManifestError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ManifestError::Io(e) => write!(f, "Manifest couldn't be read: {}", e),
+                ManifestError::Parse(e) => write!(f, "Manifest couldn't be parsed: {}", e),
+                ManifestError::Invalid(e) => write!(f, "Manifest is invalid: {}", e),
+            }
+        }
+    }
+
+    impl std::error::Error for ManifestError {}
+
+    impl ModelManifest {
+        pub fn from_env() -> Result<Self, ManifestError> {
+            Self::load(Self::path_from_env())
+        }
+
+        pub fn path_from_env() -> PathBuf {
+            PathBuf::from(env::var("MODEL_MANIFEST").unwrap_or("util/models.json".to_string()))
+        }
+
+        pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ManifestError> {
+            let raw = serde_json::to_string_pretty(self).map_err(|e| ManifestError::Parse(e.to_string()))?;
+
+            std::fs::write(path.as_ref(), raw + "\n")
+                .map_err(|e| ManifestError::Io(format!("{}: {}", path.as_ref().display(), e)))
+        }
+
+        pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ManifestError> {
+            let raw = std::fs::read_to_string(path.as_ref())
+                .map_err(|e| ManifestError::Io(format!("{}: {}", path.as_ref().display(), e)))?;
+
+            // missing model files are reported by `ModelRegistry::load`, which skips them
+            Self::parse(&raw)
+        }
+
+        pub fn parse(raw: &str) -> Result<Self, ManifestError> {
+            let manifest: ModelManifest =
+                serde_json::from_str(raw).map_err(|e| ManifestError::Parse(e.to_string()))?;
+
+            manifest.validate()?;
+
+            Ok(manifest)
+        }
+
+        pub fn entry(&self, feature: &Feature) -> &ModelEntry {
+            self.models
+                .get(feature)
+                .expect("Manifest is validated to contain every feature")
+        }
+
+        pub fn training_data(&self, feature: &Feature) -> Option<&str> {
+            self.entry(feature)
+                .training_data
+                .as_deref()
+                .or(self.training_data.as_deref())
+        }
+
+        fn validate(&self) -> Result<(), ManifestError> {
+            if self.class_labels.is_empty() {
+                return Err(ManifestError::Invalid("no class labels declared".to_string()));
+            }
+
+            let mut expected: Vec<&String> = self.class_labels.iter().collect();
+            expected.sort();
+            if expected.windows(2).any(|w| w[0] == w[1]) {
+                return Err(ManifestError::Invalid(format!(
+                    "class labels {:?} contain duplicates",
+                    self.class_labels
+                )));
+            }
+
+            for feature in Feature::all() {
+                let entry = self.models.get(&feature).ok_or(ManifestError::Invalid(format!(
+                    "no model declared for {:?}",
+                    feature
+                )))?;
+
+                if !entry.weight.is_finite() || entry.weight <= 0.0 {
+                    return Err(ManifestError::Invalid(format!(
+                        "{:?} weight must be positive, got {}",
+                        feature, entry.weight
+                    )));
+                }
+
+                if let Some(accuracy) = entry.validation_accuracy {
+                    if !(0.0..=1.0).contains(&accuracy) {
+                        return Err(ManifestError::Invalid(format!(
+                            "{:?} validation accuracy must be within 0..1, got {}",
+                            feature, accuracy
+                        )));
+                    }
+                }
+
+                if entry.input_shape.len() != 2 || entry.input_shape.iter().any(|d| *d <= 0) {
+                    return Err(ManifestError::Invalid(format!(
+                        "{:?} input shape must have two positive dimensions, got {:?}",
+                        feature, entry.input_shape
+                    )));
+                }
+
+                if !entry.calibration.temperature.is_finite() || entry.calibration.temperature <= 0.0 {
+                    return Err(ManifestError::Invalid(format!(
+                        "{:?} calibration temperature must be positive, got {}",
+                        feature, entry.calibration.temperature
+                    )));
+                }
+
+                if let Some(bias) = &entry.calibration.bias {
+                    if bias.len() != entry.class_labels.len() {
+                        return Err(ManifestError::Invalid(format!(
+                            "{:?} calibration bias has {} values for {} class labels",
+                            feature,
+                            bias.len(),
+                            entry.class_labels.len()
+                        )));
+                    }
+                }
+
+                let mut labels: Vec<&String> = entry.class_labels.iter().collect();
+                labels.sort();
+                if labels != expected {
+                    return Err(ManifestError::Invalid(format!(
+                        "{:?} class labels {:?} don't match {:?}",
+                        feature, entry.class_labels, self.class_labels
+                    )));
+                }
+            }
+
+            // a model declared twice would be counted twice by the ensemble
+            let features = Feature::all();
+            for (idx, feature) in features.iter().enumerate() {
+                let path = &self.entry(feature).path;
+                if let Some(other) = features[..idx].iter().find(|other| &self.entry(other).path == path) {
+                    return Err(ManifestError::Invalid(format!(
+                        "{:?} and {:?} share the model {}",
+                        other,
+                        feature,
+                        path.display()
+                    )));
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn manifest_json() -> serde_json::Value {
+            serde_json::from_str(include_str!("../util/models.json")).unwrap()
+        }
+
+        #[test]
+        fn parses_bundled_manifest() {
+            let manifest = ModelManifest::parse(&manifest_json().to_string()).unwrap();
+
+            assert_eq!(manifest.models.len(), Feature::all().len());
+            assert_eq!(manifest.entry(&Feature::Tonnetz).input_shape, vec![6, 44]);
+            assert!(manifest.models.values().all(|entry| entry.validation_accuracy.is_none()));
+            assert_eq!(manifest.training_data(&Feature::Ft), None);
+        }
+
+        #[test]
+        fn rejects_models_shared_between_features() {
+            let mut json = manifest_json();
+            json["models"]["spectrogram"]["path"] = json["models"]["mel_spectrogram"]["path"].clone();
+
+            let Err(ManifestError::Invalid(e)) = ModelManifest::parse(&json.to_string()) else {
+                panic!("Two features sharing a model shouldn't be accepted");
+            };
+            assert_eq!(e, "Spectrogram and MelSpectrogram share the model util/mel_spectrogram_model.pt");
+        }
+
+        #[test]
+        fn rejects_missing_feature() {
+            let mut json = manifest_json();
+            json["models"].as_object_mut().unwrap().remove("tonnetz");
+
+            assert!(matches!(
+                ModelManifest::parse(&json.to_string()),
+                Err(ManifestError::Invalid(_))
+            ));
+        }
+
+        #[test]
+        fn rejects_mismatched_class_labels() {
+            let mut json = manifest_json();
+            json["models"]["mfcc"]["class_labels"] = serde_json::json!(["Rock", "Pop"]);
+
+            assert!(matches!(
+                ModelManifest::parse(&json.to_string()),
+                Err(ManifestError::Invalid(_))
+            ));
+        }
+
+        #[test]
+        fn maps_reordered_model_labels() {
+            let mut json = manifest_json();
+            json["class_labels"] = serde_json::json!(["Pop", "Rock", "Classical", "Hip-Hop", "Electronic"]);
+
+            let manifest = ModelManifest::parse(&json.to_string()).unwrap();
+            let order = manifest.entry(&Feature::Ft).label_order(&manifest.class_labels);
+
+            assert_eq!(order, vec![3, 0, 4, 1, 2]);
+        }
+
+        #[test]
+        fn accepts_any_number_of_classes() {
+            let labels = serde_json::json!([
+                "blues", "classical", "country", "disco", "hiphop",
+                "jazz", "metal", "pop", "reggae", "rock"
+            ]);
+            let mut json = manifest_json();
+            json["class_labels"] = labels.clone();
+            for feature in json["models"].as_object_mut().unwrap().values_mut() {
+                feature["class_labels"] = labels.clone();
+            }
+
+            let manifest = ModelManifest::parse(&json.to_string()).unwrap();
+
+            assert_eq!(manifest.class_labels.len(), 10);
+        }
+
+        #[test]
+        fn calibration_defaults_to_identity() {
+            let manifest = ModelManifest::parse(&manifest_json().to_string()).unwrap();
+            let calibration = &manifest.entry(&Feature::Ft).calibration;
+
+            assert_eq!(calibration, &Calibration::default());
+            assert_eq!(calibration.apply(&[2.0, -1.0]), vec![2.0, -1.0]);
+        }
+
+        #[test]
+        fn model_training_data_overrides_manifest() {
+            let mut json = manifest_json();
+            json["training_data"] = "shared training set".into();
+            json["models"]["tonnetz"]["training_data"] = "tonnetz training set".into();
+            let manifest = ModelManifest::parse(&json.to_string()).unwrap();
+
+            assert_eq!(manifest.training_data(&Feature::Ft), Some("shared training set"));
+            assert_eq!(manifest.training_data(&Feature::Tonnetz), Some("tonnetz training set"));
+        }
+
+        #[test]
+        fn rejects_bias_of_wrong_length() {
+            let mut json = manifest_json();
+            json["models"]["ft"]["calibration"] = serde_json::json!({"temperature": 1.5, "bias": [0.1, 0.2]});
+
+            assert!(matches!(
+                ModelManifest::parse(&json.to_string()),
+                Err(ManifestError::Invalid(_))
+            ));
+        }
+
+        #[test]
+        fn rejects_non_positive_weight() {
+            let mut json = manifest_json();
+            json["models"]["ft"]["weight"] = serde_json::json!(0.0);
+
+            assert!(matches!(
+                ModelManifest::parse(&json.to_string()),
+                Err(ManifestError::Invalid(_))
+            ));
+        }
+    }
+}
+
+pub mod provenance {
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::ml::{
+        ensemble::Ensemble,
+        ml::{Feature, FeatureClassificationResult, ModelRegistry, SongClassificationResult},
+    };
+
+    /// Largest per-class probability change still considered the same result.
+    pub const DRIFT_TOLERANCE: f32 = 1e-4;
+
+    /// What a classification was computed from, so old results can be traced to model versions.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Provenance {
+        pub manifest_version: String,
+        /// Strategy the result was classified with, before any `?strategy=` recombination.
+        pub strategy: Ensemble,
+        pub features: Vec<FeatureProvenance>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct FeatureProvenance {
+        pub feature: Feature,
+        pub weight: f32,
+        pub model_sha256: String,
+        pub input_sha256: String,
+    }
+
+    impl FeatureProvenance {
+        pub fn model_short(&self) -> &str {
+            short_hash(&self.model_sha256)
+        }
+
+        pub fn input_short(&self) -> &str {
+            short_hash(&self.input_sha256)
+        }
+    }
+
+    fn short_hash(hash: &str) -> &str {
+        hash.get(..12).unwrap_or(hash)
+    }
+
+    impl Provenance {
+        pub fn new(models: &ModelRegistry, classifications: &[FeatureClassificationResult], strategy: Ensemble) -> Self {
+            Self {
+                manifest_version: models.manifest().version.clone(),
+                strategy,
+                features: classifications
+                    .iter()
+                    .map(|c| FeatureProvenance {
+                        feature: c.feature.clone(),
+                        weight: c.feature_weight,
+                        model_sha256: models.model_sha256(&c.feature).unwrap_or_default().to_string(),
+                        input_sha256: c.input_sha256.clone().unwrap_or_default(),
+                    })
+                    .collect(),
+            }
+        }
+
+        pub fn feature(&self, feature: &Feature) -> Option<&FeatureProvenance> {
+            self.features.iter().find(|f| &f.feature == feature)
+        }
+    }
+
+    /// Difference between a stored result and a fresh classification with the same strategy.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct DriftReport {
+        /// `None` for results stored before provenance was recorded.
+        pub stored_manifest_version: Option<String>,
+        pub fresh_manifest_version: String,
+        pub manifest_changed: bool,
+        pub stored_major_class: String,
+        pub fresh_major_class: String,
+        /// Largest per-class change of the ensemble distribution.
+        pub max_difference: f32,
+        pub features: Vec<FeatureDrift>,
+        pub drifted: bool,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct FeatureDrift {
+        pub feature: Feature,
+        /// `None` when the feature is only present in one of the results.
+        pub max_difference: Option<f32>,
+        /// `None` when the stored result has no provenance for the feature.
+        pub model_changed: Option<bool>,
+        pub input_changed: Option<bool>,
+    }
+
+    impl FeatureDrift {
+        pub fn drifted(&self) -> bool {
+            self.max_difference.map(|d| d > DRIFT_TOLERANCE).unwrap_or(true)
+                || self.model_changed.unwrap_or(false)
+                || self.input_changed.unwrap_or(false)
+        }
+    }
+
+    fn max_difference(a: &[f32], b: &[f32]) -> f32 {
+        if a.len() != b.len() {
+            return f32::INFINITY;
+        }
+
+        a.iter().zip(b).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+    }
+
+    impl DriftReport {
+        pub fn new(stored: &SongClassificationResult, fresh: &SongClassificationResult) -> Self {
+            let stored_provenance = stored.provenance.as_ref();
+            let fresh_provenance = fresh.provenance.as_ref();
+
+            let stored_manifest_version = stored_provenance.map(|p| p.manifest_version.clone());
+            let fresh_manifest_version = fresh_provenance.map(|p| p.manifest_version.clone()).unwrap_or_default();
+            let manifest_changed = stored_manifest_version.as_ref() != Some(&fresh_manifest_version);
+
+            let features: Vec<FeatureDrift> = Feature::all()
+                .into_iter()
+                .filter_map(|feature| {
+                    let stored_result = stored.feature_classification_result.iter().find(|c| c.feature == feature);
+                    let fresh_result = fresh.feature_classification_result.iter().find(|c| c.feature == feature);
+
+                    if stored_result.is_none() && fresh_result.is_none() {
+                        return None;
+                    }
+
+                    let max_difference = match (stored_result, fresh_result) {
+                        (Some(s), Some(f)) => Some(max_difference(&s.avg_classification, &f.avg_classification)),
+                        _ => None,
+                    };
+
+                    let stored_feature = stored_provenance.and_then(|p| p.feature(&feature));
+                    let fresh_feature = fresh_provenance.and_then(|p| p.feature(&feature));
+
+                    let (model_changed, input_changed) = match (stored_feature, fresh_feature) {
+                        (Some(s), Some(f)) => (
+                            Some(s.model_sha256 != f.model_sha256),
+                            Some(s.input_sha256 != f.input_sha256),
+                        ),
+                        _ => (None, None),
+                    };
+
+                    Some(FeatureDrift {
+                        feature,
+                        max_difference,
+                        model_changed,
+                        input_changed,
+                    })
+                })
+                .collect();
+
+            let max_difference = max_difference(&stored.cum_classification, &fresh.cum_classification);
+
+            let drifted = manifest_changed
+                || max_difference > DRIFT_TOLERANCE
+                || stored.major_class != fresh.major_class
+                || features.iter().any(|f| f.drifted());
+
+            Self {
+                stored_manifest_version,
+                fresh_manifest_version,
+                manifest_changed,
+                stored_major_class: stored.major_class.label.clone(),
+                fresh_major_class: fresh.major_class.label.clone(),
+                max_difference,
+                features,
+                drifted,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::BTreeMap;
+
+        use super::*;
+
+        fn result(probabilities: Vec<f32>, model_sha256: &str) -> SongClassificationResult {
+            let feature = FeatureClassificationResult::from_frames(Feature::Ft, 1.0, BTreeMap::from([(0, probabilities)]));
+
+            let mut result = SongClassificationResult::from_features(
+                "song".to_string(),
+                vec!["Rock".to_string(), "Pop".to_string()],
+                vec![feature],
+                Vec::new(),
+                Ensemble::WeightedMean,
+            );
+            result.provenance = Some(Provenance {
+                manifest_version: "1".to_string(),
+                strategy: Ensemble::WeightedMean,
+                features: vec![FeatureProvenance {
+                    feature: Feature::Ft,
+                    weight: 1.0,
+                    model_sha256: model_sha256.to_string(),
+                    input_sha256: "input".to_string(),
+                }],
+            });
+
+            result
+        }
+
+        #[test]
+        fn identical_results_do_not_drift() {
+            let report = DriftReport::new(&result(vec![0.7, 0.3], "a"), &result(vec![0.7, 0.3], "a"));
+
+            assert!(!report.drifted);
+            assert!(!report.manifest_changed);
+            assert_eq!(report.features[0].model_changed, Some(false));
+        }
+
+        #[test]
+        fn changed_model_and_probabilities_drift() {
+            let report = DriftReport::new(&result(vec![0.7, 0.3], "a"), &result(vec![0.4, 0.6], "b"));
+
+            assert!(report.drifted);
+            assert_eq!(report.fresh_major_class, "Pop");
+            assert_eq!(report.features[0].model_changed, Some(true));
+            assert_eq!(report.features[0].input_changed, Some(false));
+            assert!((report.max_difference - 0.3).abs() < 1e-6);
+        }
+
+        #[test]
+        fn results_without_provenance_count_as_drifted() {
+            let mut stored = result(vec![0.7, 0.3], "a");
+            stored.provenance = None;
+
+            let report = DriftReport::new(&stored, &result(vec![0.7, 0.3], "a"));
+
+            assert!(report.manifest_changed);
+            assert_eq!(report.stored_manifest_version, None);
+            assert_eq!(report.features[0].model_changed, None);
+            assert!(report.drifted);
+        }
+    }
+}
+
+pub mod similarity {
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::ml::ml::{sha256_hex, Feature, SongClassificationResult};
+
+    /// One vector per classified upload, compared by cosine similarity.
+    ///
+    /// Vectors are only comparable within the same `space`: embeddings of one manifest version,
+    /// or probabilities over one list of class labels.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct TrackVector {
+        pub space: String,
+        pub values: Vec<f32>,
+    }
+
+    impl TrackVector {
+        /// Concatenated embeddings when every feature model exposed one, the averaged probabilities otherwise.
+        pub fn from_result(result: &SongClassificationResult) -> Self {
+            Self::embeddings(result).unwrap_or_else(|| Self::probabilities(result))
+        }
+
+        /// Each feature's embedding is L2-normalized first, so features with wider layers don't dominate.
+        fn embeddings(result: &SongClassificationResult) -> Option<Self> {
+            let provenance = result.provenance.as_ref()?;
+
+            if !result.missing_features.is_empty() {
+                return None;
+            }
+
+            let mut values = Vec::new();
+
+            for feature in Feature::all() {
+                let embedding = result
+                    .feature_classification_result
+                    .iter()
+                    .find(|c| c.feature == feature)?
+                    .embedding
+                    .as_ref()?;
+
+                let norm = norm(embedding);
+                if norm == 0.0 {
+                    return None;
+                }
+
+                values.extend(embedding.iter().map(|v| v / norm));
+            }
+
+            Some(Self {
+                space: format!("embedding:{}", provenance.manifest_version),
+                values,
+            })
+        }
+
+        /// Missing features are filled with the ensemble distribution to keep every vector the same length.
+        fn probabilities(result: &SongClassificationResult) -> Self {
+            let values = Feature::all()
+                .iter()
+                .flat_map(|feature| {
+                    result
+                        .feature_classification_result
+                        .iter()
+                        .find(|c| &c.feature == feature)
+                        .map(|c| c.avg_classification.clone())
+                        .unwrap_or_else(|| result.cum_classification.clone())
+                })
+                .collect();
+
+            let labels_sha256 = sha256_hex(result.class_labels.join("\n").as_bytes());
+
+            Self {
+                space: format!("probabilities:{}", &labels_sha256[..12]),
+                values,
+            }
+        }
+
+        pub fn norm(&self) -> f32 {
+            norm(&self.values)
+        }
+    }
+
+    fn norm(values: &[f32]) -> f32 {
+        values.iter().map(|v| v * v).sum::<f32>().sqrt()
+    }
+
+    /// Which uploads a similar-track search looks through.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum SimilarityScope {
+        /// Uploads of the same user.
+        #[default]
+        User,
+        /// Uploads of the same user and every shared upload.
+        Library,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::BTreeMap;
+
+        use super::*;
+        use crate::ml::{
+            ensemble::Ensemble,
+            ml::{FeatureClassificationResult, MissingFeature},
+            provenance::Provenance,
+        };
+
+        fn result(embedding: Option<Vec<f32>>, missing: Option<Feature>) -> SongClassificationResult {
+            let classifications = Feature::all()
+                .into_iter()
+                .filter(|feature| Some(feature) != missing.as_ref())
+                .map(|feature| {
+                    let mut c = FeatureClassificationResult::from_frames(feature, 1.0, BTreeMap::from([(0, vec![0.75, 0.25])]));
+                    c.embedding = embedding.clone();
+                    c
+                })
+                .collect();
+
+            let missing_features = missing
+                .into_iter()
+                .map(|feature| MissingFeature {
+                    feature,
+                    reason: "not found".to_string(),
+                })
+                .collect();
+
+            let mut result = SongClassificationResult::from_features(
+                "song".to_string(),
+                vec!["Rock".to_string(), "Pop".to_string()],
+                classifications,
+                missing_features,
+                Ensemble::WeightedMean,
+            );
+            result.provenance = Some(Provenance {
+                manifest_version: "3".to_string(),
+                strategy: Ensemble::WeightedMean,
+                features: Vec::new(),
+            });
+
+            result
+        }
+
+        #[test]
+        fn uses_normalized_embeddings_when_every_model_exposes_them() {
+            let vector = TrackVector::from_result(&result(Some(vec![3.0, 4.0]), None));
+
+            assert_eq!(vector.space, "embedding:3");
+            assert_eq!(vector.values.len(), 2 * Feature::all().len());
+            assert_eq!(&vector.values[..2], &[0.6, 0.8]);
+            assert!((vector.norm() - 3.0).abs() < 1e-6);
+        }
+
+        #[test]
+        fn falls_back_to_probabilities() {
+            let without_embeddings = TrackVector::from_result(&result(None, None));
+            let with_missing_feature = TrackVector::from_result(&result(Some(vec![3.0, 4.0]), Some(Feature::Tonnetz)));
+
+            assert!(without_embeddings.space.starts_with("probabilities:"));
+            assert_eq!(without_embeddings.space, with_missing_feature.space);
+            assert_eq!(without_embeddings.values.len(), with_missing_feature.values.len());
+            assert_eq!(&without_embeddings.values[..2], &[0.75, 0.25]);
+        }
+    }
+}
+
+pub mod ablation {
+
+    use serde::Serialize;
+
+    use crate::ml::{
+        ensemble::{argmax, Ensemble},
+        ml::{Class, Feature, SongClassificationResult},
+    };
+
+    /// What the ensemble would have decided without each feature, one at a time.
+    /// Reuses the per-feature results, no model is run.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct AblationReport {
+        pub strategy: Ensemble,
+        pub class_labels: Vec<String>,
+        pub cum_classification: Vec<f32>,
+        pub major_class: Class,
+        /// In the order of the result's features.
+        pub features: Vec<FeatureAblation>,
+        /// Features whose removal changes `major_class`.
+        pub decisive_features: Vec<Feature>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct FeatureAblation {
+        pub feature: Feature,
+        pub feature_weight: f32,
+        /// Ensemble distribution without the feature, the other weights renormalized.
+        /// `None` when it is the only classified feature.
+        pub cum_classification: Option<Vec<f32>>,
+        pub major_class: Option<Class>,
+        pub major_class_changed: bool,
+        /// How much the full result's `major_class` loses without the feature, negative when it gains.
+        pub major_class_drop: f32,
+        /// Largest per-class change of the ensemble distribution.
+        pub max_difference: f32,
+    }
+
+    impl AblationReport {
+        pub fn new(result: &SongClassificationResult) -> Self {
+            let major = result.major_class.index;
+
+            let features: Vec<FeatureAblation> = result
+                .feature_classification_result
+                .iter()
+                .map(|ablated| {
+                    let others: Vec<_> = result
+                        .feature_classification_result
+                        .iter()
+                        .filter(|c| c.feature != ablated.feature)
+                        .cloned()
+                        .collect();
+
+                    if others.is_empty() {
+                        return FeatureAblation {
+                            feature: ablated.feature.clone(),
+                            feature_weight: ablated.feature_weight,
+                            cum_classification: None,
+                            major_class: None,
+                            major_class_changed: true,
+                            major_class_drop: result.cum_classification.get(major).copied().unwrap_or(0.0),
+                            max_difference: 1.0,
+                        };
+                    }
+
+                    let cum_classification = result.strategy.combine(&others);
+                    let index = argmax(&cum_classification);
+
+                    let major_class_drop = result.cum_classification.get(major).copied().unwrap_or(0.0)
+                        - cum_classification.get(major).copied().unwrap_or(0.0);
+
+                    let max_difference = result
+                        .cum_classification
+                        .iter()
+                        .zip(&cum_classification)
+                        .map(|(a, b)| (a - b).abs())
+                        .fold(0.0, f32::max);
+
+                    FeatureAblation {
+                        feature: ablated.feature.clone(),
+                        feature_weight: ablated.feature_weight,
+                        major_class: result.class_labels.get(index).map(|label| Class {
+                            index,
+                            label: label.clone(),
+                        }),
+                        major_class_changed: index != major,
+                        cum_classification: Some(cum_classification),
+                        major_class_drop,
+                        max_difference,
+                    }
+                })
+                .collect();
+
+            let decisive_features = features
+                .iter()
+                .filter(|f| f.major_class_changed)
+                .map(|f| f.feature.clone())
+                .collect();
+
+            Self {
+                strategy: result.strategy,
+                class_labels: result.class_labels.clone(),
+                cum_classification: result.cum_classification.clone(),
+                major_class: result.major_class.clone(),
+                features,
+                decisive_features,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::BTreeMap;
+
+        use super::*;
+        use crate::ml::ml::FeatureClassificationResult;
+
+        fn result(features: Vec<(Feature, f32, Vec<f32>)>) -> SongClassificationResult {
+            let classifications = features
+                .into_iter()
+                .map(|(feature, weight, probabilities)| {
+                    FeatureClassificationResult::from_frames(feature, weight, BTreeMap::from([(0, probabilities)]))
+                })
+                .collect();
+
+            SongClassificationResult::from_features(
+                "song".to_string(),
+                vec!["Rock".to_string(), "Pop".to_string()],
+                classifications,
+                Vec::new(),
+                Ensemble::WeightedMean,
+            )
+        }
+
+        #[test]
+        fn removing_the_dominant_feature_flips_the_decision() {
+            let report = AblationReport::new(&result(vec![
+                (Feature::Ft, 2.0, vec![0.9, 0.1]),
+                (Feature::Mfcc, 1.0, vec![0.3, 0.7]),
+            ]));
+
+            assert_eq!(report.major_class.label, "Rock");
+            assert_eq!(report.decisive_features, vec![Feature::Ft]);
+
+            let without_ft = &report.features[0];
+            assert_eq!(without_ft.major_class.as_ref().unwrap().label, "Pop");
+            assert!((without_ft.major_class_drop - 0.4).abs() < 1e-6);
+
+            let without_mfcc = &report.features[1];
+            assert!(!without_mfcc.major_class_changed);
+            assert!((without_mfcc.major_class_drop + 0.2).abs() < 1e-6);
+        }
+
+        #[test]
+        fn a_single_feature_has_nothing_left_to_combine() {
+            let report = AblationReport::new(&result(vec![(Feature::Ft, 1.0, vec![0.9, 0.1])]));
+
+            assert_eq!(report.features[0].cum_classification, None);
+            assert!(report.features[0].major_class_changed);
+        }
+    }
+}
+
+pub mod segments {
+
+    use ndarray::Array3;
+    use serde::{Deserialize, Serialize};
+    use sha2::{Digest, Sha256};
+
+    use crate::features::{prepare_sample, split_to_frames, FeatureExtractor, NormalizationStats, SAMPLE_SECONDS};
+    use crate::ml::{
+        ensemble::{argmax, Ensemble},
+        ml::{
+            model_input, Class, Feature, FeatureClassificationResult, Genres, MissingFeature, ModelRegistry,
+            SongClassificationResult, SAMPLE_RATE,
+        },
+    };
+
+    /// Length of every analysed window, the same as the middle sample the ETL cuts.
+    pub const SEGMENT_SECONDS: usize = SAMPLE_SECONDS;
+
+    /// Shortest rest at the end of a track that is still analysed.
+    pub const MIN_TAIL_SECONDS: usize = 10;
+
+    /// Sample range of one window of the canonical signal.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SegmentBounds {
+        pub index: usize,
+        pub start: usize,
+        pub end: usize,
+    }
+
+    impl SegmentBounds {
+        pub fn start_seconds(&self) -> f32 {
+            self.start as f32 / SAMPLE_RATE as f32
+        }
+
+        pub fn end_seconds(&self) -> f32 {
+            self.end as f32 / SAMPLE_RATE as f32
+        }
+    }
+
+    /// Consecutive `SEGMENT_SECONDS` windows over the whole track. A rest of at least
+    /// `MIN_TAIL_SECONDS` gets one more window ending with the track, overlapping the one
+    /// before it; shorter rests are left out.
+    pub fn segment_bounds(samples: usize) -> Vec<SegmentBounds> {
+        let sr = SAMPLE_RATE as usize;
+        let length = SEGMENT_SECONDS * sr;
+
+        let mut bounds: Vec<SegmentBounds> = (0..samples / length)
+            .map(|index| SegmentBounds {
+                index,
+                start: index * length,
+                end: (index + 1) * length,
+            })
+            .collect();
+
+        if samples % length >= MIN_TAIL_SECONDS * sr {
+            bounds.push(SegmentBounds {
+                index: bounds.len(),
+                start: samples.saturating_sub(length),
+                end: samples,
+            });
+        }
+
+        bounds
+    }
+
+    /// Extracts the features of one window and classifies them. Features are handed over one at
+    /// a time, so only one of them is held in memory.
+    pub fn classify_segment(
+        models: &ModelRegistry,
+        extractor: &mut FeatureExtractor,
+        stats: &NormalizationStats,
+        signal: &[f32],
+        bounds: &SegmentBounds,
+        strategy: Ensemble,
+        song_id: &str,
+    ) -> Result<SongClassificationResult, Vec<MissingFeature>> {
+        let missing_all = |reason: String| -> Vec<MissingFeature> {
+            Feature::all()
+                .into_iter()
+                .map(|feature| MissingFeature {
+                    feature,
+                    reason: reason.clone(),
+                })
+                .collect()
+        };
+
+        let frames = match prepare_sample(&signal[bounds.start..bounds.end]) {
+            Ok(sample) => split_to_frames(&sample),
+            Err(e) => return Err(missing_all(e.to_string())),
+        };
+
+        let mut classifications: Vec<FeatureClassificationResult> = Vec::new();
+        let mut missing_features: Vec<MissingFeature> = Vec::new();
+
+        let extracted = extractor.transform_all(&frames, stats, |feature, values| {
+            let input_sha256 = values_sha256(&values);
+            let input_shape = &models.manifest().entry(&feature).input_shape;
+
+            match model_input(values, &feature, input_shape)
+                .and_then(|input| FeatureClassificationResult::from_input(models, &feature, &input, input_sha256))
+            {
+                Ok(classification) => classifications.push(classification),
+                Err(e) => missing_features.push(MissingFeature {
+                    feature,
+                    reason: e.to_string(),
+                }),
+            }
+
+            Ok(())
+        });
+
+        // the features after the one that failed weren't extracted
+        if let Err(e) = extracted {
+            for missing in missing_all(e.to_string()) {
+                let seen = classifications.iter().any(|c| c.feature == missing.feature)
+                    || missing_features.iter().any(|m| m.feature == missing.feature);
+                if !seen {
+                    missing_features.push(missing);
+                }
+            }
+        }
+
+        SongClassificationResult::from_classifications(
+            models,
+            format!("{}#{}", song_id, bounds.index),
+            classifications,
+            missing_features,
+            strategy,
+        )
+    }
+
+    /// SHA-256 of the little-endian values, standing in for the npy file hash of stored features.
+    fn values_sha256(values: &Array3<f32>) -> String {
+        let mut hasher = Sha256::new();
+        values.iter().for_each(|v| hasher.update(v.to_le_bytes()));
+
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Averaged result of one feature model over a segment.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct SegmentFeature {
+        pub feature: Feature,
+        pub feature_weight: f32,
+        pub avg_classification: Vec<f32>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct Segment {
+        pub index: usize,
+        pub start_seconds: f32,
+        pub end_seconds: f32,
+        /// Empty when none of the features could be classified.
+        pub cum_classification: Vec<f32>,
+        pub major_class: Option<Class>,
+        pub genres: Option<Genres>,
+        pub features: Vec<SegmentFeature>,
+        pub missing_features: Vec<MissingFeature>,
+    }
+
+    impl Segment {
+        pub fn new(
+            index: usize,
+            start_seconds: f32,
+            end_seconds: f32,
+            class_labels: &[String],
+            cum_classification: Vec<f32>,
+            features: Vec<SegmentFeature>,
+            missing_features: Vec<MissingFeature>,
+        ) -> Self {
+            let genres = (!cum_classification.is_empty())
+                .then(|| Genres::new(&cum_classification, class_labels, Genres::threshold()));
+
+            Self {
+                index,
+                start_seconds,
+                end_seconds,
+                major_class: genres.as_ref().and_then(|g| g.ranked.first()).map(|g| g.class.clone()),
+                genres,
+                cum_classification,
+                features,
+                missing_features,
+            }
+        }
+
+        pub fn from_result(bounds: &SegmentBounds, result: &SongClassificationResult) -> Self {
+            let features = result
+                .feature_classification_result
+                .iter()
+                .map(|c| SegmentFeature {
+                    feature: c.feature.clone(),
+                    feature_weight: c.feature_weight,
+                    avg_classification: c.avg_classification.clone(),
+                })
+                .collect();
+
+            Self::new(
+                bounds.index,
+                bounds.start_seconds(),
+                bounds.end_seconds(),
+                &result.class_labels,
+                result.cum_classification.clone(),
+                features,
+                result.missing_features.clone(),
+            )
+        }
+
+        pub fn unclassified(bounds: &SegmentBounds, missing_features: Vec<MissingFeature>) -> Self {
+            Self::new(
+                bounds.index,
+                bounds.start_seconds(),
+                bounds.end_seconds(),
+                &[],
+                Vec::new(),
+                Vec::new(),
+                missing_features,
+            )
+        }
+
+        pub fn time_range(&self) -> String {
+            time_range(self.start_seconds, self.end_seconds)
+        }
+    }
+
+    /// "m:ss–m:ss".
+    pub fn time_range(start_seconds: f32, end_seconds: f32) -> String {
+        let format = |seconds: f32| {
+            let seconds = seconds.round() as u32;
+            format!("{}:{:02}", seconds / 60, seconds % 60)
+        };
+
+        format!("{}–{}", format(start_seconds), format(end_seconds))
+    }
+
+    /// Consecutive segments with the same genre.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct GenreSection {
+        pub start_seconds: f32,
+        pub end_seconds: f32,
+        pub class: Class,
+        pub segments: usize,
+    }
+
+    impl GenreSection {
+        pub fn time_range(&self) -> String {
+            time_range(self.start_seconds, self.end_seconds)
+        }
+    }
+
+    /// Merges neighbouring segments with the same major class; unclassified segments end a section.
+    /// Overlapping segments hand over at the start of the later one.
+    pub fn genre_sections(segments: &[Segment]) -> Vec<GenreSection> {
+        let mut sections: Vec<GenreSection> = Vec::new();
+        let mut previous_classified = false;
+
+        for segment in segments {
+            let Some(class) = &segment.major_class else {
+                previous_classified = false;
+                continue;
+            };
+
+            match sections.last_mut() {
+                Some(section) if previous_classified && &section.class == class => {
+                    section.end_seconds = segment.end_seconds;
+                    section.segments += 1;
+                }
+                last => {
+                    if let Some(section) = last {
+                        section.end_seconds = section.end_seconds.min(segment.start_seconds).max(section.start_seconds);
+                    }
+                    sections.push(GenreSection {
+                        start_seconds: segment.start_seconds,
+                        end_seconds: segment.end_seconds,
+                        class: class.clone(),
+                        segments: 1,
+                    });
+                }
+            }
+
+            previous_classified = true;
+        }
+
+        sections
+    }
+
+    /// Whole-track view of the stored segments of an upload.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct SegmentAnalysis {
+        pub class_labels: Vec<String>,
+        pub strategy: Ensemble,
+        /// Segments the track is split into, `segments` holds the ones analysed so far.
+        pub segment_count: usize,
+        pub segments: Vec<Segment>,
+        pub sections: Vec<GenreSection>,
+        /// e.g. "0:00–0:30 Classical, 0:30–2:00 Rock".
+        pub genre_track: String,
+        /// Segment distributions averaged over the time each one adds to the ones before it.
+        pub cum_classification: Vec<f32>,
+        pub major_class: Option<Class>,
+        pub finished: bool,
+        pub error: Option<String>,
+    }
+
+    impl SegmentAnalysis {
+        pub fn new(
+            class_labels: Vec<String>,
+            strategy: Ensemble,
+            segment_count: usize,
+            mut segments: Vec<Segment>,
+            finished: bool,
+            error: Option<String>,
+        ) -> Self {
+            segments.sort_by_key(|segment| segment.index);
+
+            let sections = genre_sections(&segments);
+
+            let genre_track = sections
+                .iter()
+                .map(|section| format!("{} {}", section.time_range(), section.class))
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            let mut cum_classification = vec![0.0; class_labels.len()];
+            let mut covered = 0.0;
+            let mut previous_end = 0.0f32;
+
+            for segment in &segments {
+                let seconds = (segment.end_seconds - segment.start_seconds.max(previous_end)).max(0.0);
+                previous_end = previous_end.max(segment.end_seconds);
+
+                if segment.cum_classification.len() != class_labels.len() {
+                    continue;
+                }
+
+                segment
+                    .cum_classification
+                    .iter()
+                    .enumerate()
+                    .for_each(|(idx, p)| cum_classification[idx] += p * seconds);
+                covered += seconds;
+            }
+
+            let (cum_classification, major_class) = if covered > 0.0 {
+                let cum: Vec<f32> = cum_classification.iter().map(|p| p / covered).collect();
+                let index = argmax(&cum);
+                let major_class = class_labels.get(index).map(|label| Class {
+                    index,
+                    label: label.clone(),
+                });
+                (cum, major_class)
+            } else {
+                (Vec::new(), None)
+            };
+
+            Self {
+                class_labels,
+                strategy,
+                segment_count,
+                segments,
+                sections,
+                genre_track,
+                cum_classification,
+                major_class,
+                finished,
+                error,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const SR: usize = SAMPLE_RATE as usize;
+
+        fn labels() -> Vec<String> {
+            vec!["Rock".to_string(), "Classical".to_string()]
+        }
+
+        fn segment(index: usize, start: f32, end: f32, cum_classification: Vec<f32>) -> Segment {
+            Segment::new(index, start, end, &labels(), cum_classification, Vec::new(), Vec::new())
+        }
+
+        #[test]
+        fn windows_cover_the_track_and_short_rests_are_dropped() {
+            let bounds = segment_bounds(75 * SR);
+
+            assert_eq!(bounds.len(), 3);
+            assert_eq!((bounds[1].start, bounds[1].end), (30 * SR, 60 * SR));
+            // the 15 s rest is analysed as the last 30 s of the track
+            assert_eq!((bounds[2].start, bounds[2].end), (45 * SR, 75 * SR));
+
+            assert_eq!(segment_bounds(65 * SR).len(), 2);
+        }
+
+        #[test]
+        fn neighbouring_segments_of_one_genre_form_a_section() {
+            let analysis = SegmentAnalysis::new(
+                labels(),
+                Ensemble::WeightedMean,
+                4,
+                vec![
+                    segment(0, 0.0, 30.0, vec![0.2, 0.8]),
+                    segment(1, 30.0, 60.0, vec![0.7, 0.3]),
+                    segment(2, 60.0, 90.0, vec![0.9, 0.1]),
+                    segment(3, 75.0, 105.0, vec![0.6, 0.4]),
+                ],
+                true,
+                None,
+            );
+
+            let sections: Vec<(&str, usize)> =
+                analysis.sections.iter().map(|s| (s.class.label.as_str(), s.segments)).collect();
+            assert_eq!(sections, vec![("Classical", 1), ("Rock", 3)]);
+            assert_eq!(analysis.genre_track, "0:00–0:30 Classical, 0:30–1:45 Rock");
+
+            // the last segment only adds the 15 s after the third one
+            let rock = (0.2 * 30.0 + 0.7 * 30.0 + 0.9 * 30.0 + 0.6 * 15.0) / 105.0;
+            assert!((analysis.cum_classification[0] - rock).abs() < 1e-6);
+            assert_eq!(analysis.major_class.unwrap().label, "Rock");
+        }
+
+        #[test]
+        fn unclassified_segments_split_sections() {
+            let silent = SegmentBounds { index: 1, start: 30 * SR, end: 60 * SR };
+
+            let analysis = SegmentAnalysis::new(
+                labels(),
+                Ensemble::WeightedMean,
+                3,
+                vec![
+                    segment(0, 0.0, 30.0, vec![0.9, 0.1]),
+                    Segment::unclassified(&silent, Vec::new()),
+                    segment(2, 60.0, 90.0, vec![0.8, 0.2]),
+                ],
+                true,
+                None,
+            );
+
+            assert_eq!(analysis.sections.len(), 2);
+            assert!((analysis.cum_classification[0] - 0.85).abs() < 1e-6);
+        }
+    }
+}
+
+pub mod saliency {
+
+    use std::{
+        ops::Range,
+        path::{Path, PathBuf},
+    };
+
+    use ndarray::{s, Array2};
+
+    use crate::ml::ml::{calibrate, softmax, CustomError, Feature, ModelRegistry, FRAME_HOP, SAMPLE_RATE};
+
+    /// Most patches occluded along the frequency and time axes of one frame.
+    const PATCHES_PER_AXIS: [usize; 2] = [16, 8];
+    /// Images of features with few rows (chroma, tonnetz) are stretched to at least this height.
+    const MIN_IMAGE_HEIGHT: usize = 240;
+
+    /// Occlusion saliency of one feature model for one class across the 30 s sample.
+    /// `values` is `(a, seconds * b)` for `(a, b)` model inputs: every non-overlapping 1 s frame
+    /// laid next to each other, so columns follow the sample's time axis.
+    #[derive(Debug, Clone)]
+    pub struct SaliencyMap {
+        pub feature: Feature,
+        /// Index into the manifest's `class_labels`.
+        pub class: usize,
+        pub values: Array2<f32>,
+    }
+
+    /// Cached image of a map: one per upload, feature, class and model file.
+    pub fn saliency_path(server_data: &Path, upload_uuid: &str, feature: &Feature, class: usize, model_sha256: &str) -> PathBuf {
+        let model = &model_sha256[..model_sha256.len().min(12)];
+
+        server_data
+            .join("saliency")
+            .join(upload_uuid)
+            .join(format!("{}_{}_{}.png", feature.key(), class, model))
+    }
+
+    /// How much each region of the input supports `class`: the drop in the model's calibrated
+    /// probability for `class` when the region is set to zero, the training mean of
+    /// standardized features. Negative values mark regions that speak against it.
+    ///
+    /// Only frames one second apart are occluded, which covers the sample without overlap.
+    pub fn occlusion(
+        models: &ModelRegistry,
+        feature: &Feature,
+        frames: &Array2<f32>,
+        class: usize,
+    ) -> Result<SaliencyMap, CustomError> {
+        let entry = models.manifest().entry(feature);
+        let (rows, columns) = (entry.input_shape[0] as usize, entry.input_shape[1] as usize);
+
+        let label_order = entry.label_order(&models.manifest().class_labels);
+        let target = *label_order
+            .get(class)
+            .ok_or_else(|| CustomError(format!("No class {} in the manifest", class)))?;
+
+        let patch = [
+            rows.div_ceil(PATCHES_PER_AXIS[0]).max(1),
+            columns.div_ceil(PATCHES_PER_AXIS[1]).max(1),
+        ];
+        // row and column ranges, the last ones cut at the edge of the input
+        let patches: Vec<(Range<usize>, Range<usize>)> = (0..rows)
+            .step_by(patch[0])
+            .flat_map(|row| {
+                (0..columns)
+                    .step_by(patch[1])
+                    .map(move |column| (row..(row + patch[0]).min(rows), column..(column + patch[1]).min(columns)))
+            })
+            .collect();
+
+        let stride = (SAMPLE_RATE / FRAME_HOP) as usize;
+        let selected: Vec<usize> = (0..frames.nrows()).step_by(stride).collect();
+        let mut values = Array2::zeros((rows, selected.len() * columns));
+
+        for (second, frame) in selected.iter().enumerate() {
+            // the untouched frame first, then one copy per occluded patch
+            let mut batch = Array2::zeros((patches.len() + 1, rows * columns));
+            for mut occluded in batch.outer_iter_mut() {
+                occluded.assign(&frames.row(*frame));
+            }
+            for (idx, (row, column)) in patches.iter().enumerate() {
+                let mut occluded = batch
+                    .row_mut(idx + 1)
+                    .into_shape_with_order((rows, columns))
+                    .map_err(|e| CustomError(e.to_string()))?;
+                occluded.slice_mut(s![row.clone(), column.clone()]).fill(0.0);
+            }
+
+            let probabilities = softmax(calibrate(models.forward(feature, &batch)?, &entry.calibration));
+            let baseline = probabilities[[0, target]];
+
+            let mut second_values = values.slice_mut(s![.., second * columns..(second + 1) * columns]);
+            for (idx, (row, column)) in patches.iter().enumerate() {
+                let drop = baseline - probabilities[[idx + 1, target]];
+                second_values.slice_mut(s![row.clone(), column.clone()]).fill(drop);
+            }
+        }
+
+        Ok(SaliencyMap {
+            feature: feature.clone(),
+            class,
+            values,
+        })
+    }
+
+    impl SaliencyMap {
+        /// RGB PNG with the first input row (lowest frequency or coefficient) at the bottom.
+        /// Support for the class is drawn from black to yellow, counter-evidence from black
+        /// to blue, both scaled by the largest absolute value.
+        pub fn to_png(&self) -> Result<Vec<u8>, CustomError> {
+            let (rows, columns) = self.values.dim();
+            let scale = MIN_IMAGE_HEIGHT.div_ceil(rows).max(1);
+            let peak = self.values.fold(0.0f32, |a, b| a.max(b.abs()));
+
+            let mut pixels = Vec::with_capacity(rows * scale * columns * 3);
+            for row in (0..rows).rev() {
+                let line: Vec<u8> = self
+                    .values
+                    .row(row)
+                    .iter()
+                    .flat_map(|value| colour(if peak > 0.0 { value / peak } else { 0.0 }))
+                    .collect();
+
+                for _ in 0..scale {
+                    pixels.extend_from_slice(&line);
+                }
+            }
+
+            let mut png = Vec::new();
+            let mut encoder = png::Encoder::new(&mut png, columns as u32, (rows * scale) as u32);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+
+            let mut writer = encoder.write_header().map_err(|e| CustomError(e.to_string()))?;
+            writer.write_image_data(&pixels).map_err(|e| CustomError(e.to_string()))?;
+            writer.finish().map_err(|e| CustomError(e.to_string()))?;
+
+            Ok(png)
+        }
+    }
+
+    /// `value` in [-1, 1].
+    fn colour(value: f32) -> [u8; 3] {
+        let level = (value.abs().clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        if value >= 0.0 {
+            [level, (level as f32 * 0.85) as u8, 0]
+        } else {
+            [0, (level as f32 * 0.4) as u8, level]
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::HashMap;
+
+        use ndarray::Array2;
+
+        use super::*;
+        use crate::ml::{backend::ModelBackend, manifest::ModelManifest};
+
+        /// Votes for the first class with the mean of the top-left quarter of its input.
+        struct TopLeftModel {
+            rows: usize,
+            columns: usize,
+            classes: usize,
+        }
+
+        impl ModelBackend for TopLeftModel {
+            fn forward(&self, frames: &Array2<f32>) -> Result<Array2<f32>, CustomError> {
+                let mut logits = Array2::zeros((frames.nrows(), self.classes));
+
+                for (idx, frame) in frames.outer_iter().enumerate() {
+                    let input = frame.into_shape_with_order((self.rows, self.columns)).unwrap();
+                    logits[[idx, 0]] = input.slice(s![..self.rows / 2, ..self.columns / 2]).mean().unwrap() * 10.0;
+                }
+
+                Ok(logits)
+            }
+
+            fn name(&self) -> &'static str {
+                "test"
+            }
+        }
+
+        fn models() -> ModelRegistry {
+            let manifest = ModelManifest::parse(include_str!("../util/models.json")).unwrap();
+            let entry = manifest.entry(&Feature::Tonnetz);
+            let model = TopLeftModel {
+                rows: entry.input_shape[0] as usize,
+                columns: entry.input_shape[1] as usize,
+                classes: entry.class_labels.len(),
+            };
+
+            ModelRegistry::new(manifest, HashMap::from([(Feature::Tonnetz, Box::new(model) as Box<dyn ModelBackend>)]))
+        }
+
+        #[test]
+        fn occlusion_highlights_the_region_the_model_looks_at() {
+            let models = models();
+            let [rows, columns] = [6, 44];
+            let frames = Array2::ones((291, rows * columns));
+
+            let map = occlusion(&models, &Feature::Tonnetz, &frames, 0).unwrap();
+
+            assert_eq!(map.values.dim(), (rows, 30 * columns));
+            assert!(map.values[[0, 0]] > 0.0);
+            assert!(map.values[[0, 29 * columns]] > 0.0);
+            assert_eq!(map.values[[rows - 1, columns - 1]], 0.0);
+            assert_eq!(map.values[[0, columns - 1]], 0.0);
+        }
+
+        #[test]
+        fn png_is_stretched_for_short_features() {
+            let map = SaliencyMap {
+                feature: Feature::Tonnetz,
+                class: 0,
+                values: Array2::from_shape_fn((6, 44), |(row, _)| row as f32 - 2.5),
+            };
+
+            let png = map.to_png().unwrap();
+            let decoder = png::Decoder::new(std::io::Cursor::new(png));
+            let reader = decoder.read_info().unwrap();
+
+            assert_eq!(reader.info().width, 44);
+            assert_eq!(reader.info().height, 240);
+        }
+    }
+}
+
+pub mod inference {
+
+    use std::{
+        env, fmt,
+        path::PathBuf,
+        sync::{Arc, RwLock},
+        time::{Duration, SystemTime},
+    };
+
+    use tokio::sync::{Mutex, Semaphore};
+
+    use crate::ml::{
+        ensemble::Ensemble,
+        manifest::{ManifestError, ModelManifest},
+        ml::{MissingFeature, ModelRegistry, SongClassificationResult},
+    };
+
+    /// Runs classifications on tokio's blocking threads, at most `workers` at a time.
+    /// Up to `queue` further requests wait for a worker; anything beyond that is turned away.
+    #[derive(Clone)]
+    pub struct InferencePool {
+        /// Swapped as a whole on reload; every job keeps the registry it started with.
+        models: Arc<RwLock<Arc<ModelRegistry>>>,
+        workers: Arc<Semaphore>,
+        admitted: Arc<Semaphore>,
+        reloading: Arc<Mutex<()>>,
+    }
+
+    #[derive(Debug)]
+    pub enum InferenceError {
+        /// Every worker is busy and the queue is full.
+        Busy,
+        /// The classification panicked.
+        Failed(String),
+        /// None of the features could be classified.
+        NoFeatures(Vec<MissingFeature>),
+        /// The upload's stored name can't be used as a path under `SERVER_DATA`.
+        InvalidUpload(String),
+    }
+
+    impl fmt::Display for InferenceError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                InferenceError::Busy => write!(f, "All classification workers are busy, try again later"),
+                InferenceError::Failed(e) => write!(f, "Classification failed: {}", e),
+                InferenceError::InvalidUpload(upload_name) => write!(f, "Upload {:?} has no usable file name", upload_name),
+                InferenceError::NoFeatures(missing) => {
+                    write!(f, "No feature could be classified")?;
+                    for m in missing {
+                        write!(f, "; {}: {}", m.feature.key(), m.reason)?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    impl std::error::Error for InferenceError {}
+
+    impl InferencePool {
+        pub fn new(models: Arc<ModelRegistry>, workers: usize, queue: usize) -> Self {
+            let workers = workers.max(1);
+
+            Self {
+                models: Arc::new(RwLock::new(models)),
+                workers: Arc::new(Semaphore::new(workers)),
+                admitted: Arc::new(Semaphore::new(workers + queue)),
+                reloading: Arc::new(Mutex::new(())),
+            }
+        }
+
+        /// Sized by `INFERENCE_WORKERS` (available cores by default) and `INFERENCE_QUEUE` (8 by default).
+        pub fn from_env(models: Arc<ModelRegistry>) -> Self {
+            let workers = env::var("INFERENCE_WORKERS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+
+            let queue = env::var("INFERENCE_QUEUE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(8);
+
+            Self::new(models, workers, queue)
+        }
+
+        /// The registry new jobs run against.
+        pub fn models(&self) -> Arc<ModelRegistry> {
+            self.models.read().expect("Registry lock should not be poisoned").clone()
+        }
+
+        pub fn swap(&self, models: Arc<ModelRegistry>) {
+            *self.models.write().expect("Registry lock should not be poisoned") = models;
+        }
+
+        /// Loads and validates the manifest at `MODEL_MANIFEST` and its models on a blocking thread,
+        /// then swaps them in. Refused when a feature that currently has a model would lose it.
+        pub async fn reload(&self) -> Result<Arc<ModelRegistry>, ManifestError> {
+            let _reloading = self.reloading.lock().await;
+
+            let registry = tokio::task::spawn_blocking(|| ModelManifest::from_env().and_then(ModelRegistry::load))
+                .await
+                .map_err(|e| ManifestError::Invalid(format!("Reload panicked: {}", e)))??;
+
+            let lost: Vec<String> = self
+                .models()
+                .available_features()
+                .iter()
+                .filter(|feature| registry.unavailable().contains_key(feature))
+                .map(|feature| format!("{}: {}", feature.key(), registry.unavailable()[feature]))
+                .collect();
+
+            if !lost.is_empty() {
+                return Err(ManifestError::Invalid(format!(
+                    "Reload would drop working models, keeping the current ones. {}",
+                    lost.join("; ")
+                )));
+            }
+
+            let registry = Arc::new(registry);
+            self.swap(registry.clone());
+
+            tracing::info!("Models reloaded, manifest version {}", registry.manifest().version);
+
+            Ok(registry)
+        }
+
+        /// Checks the manifest and model files every `MODEL_RELOAD_INTERVAL` seconds and reloads
+        /// once any of them changed. Not started when the variable is unset or 0.
+        pub fn watch_from_env(&self) {
+            let interval = env::var("MODEL_RELOAD_INTERVAL")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(0);
+
+            if interval == 0 {
+                return;
+            }
+
+            let pool = self.clone();
+
+            tokio::spawn(async move {
+                let mut last_seen = model_files_fingerprint();
+
+                loop {
+                    tokio::time::sleep(Duration::from_secs(interval)).await;
+
+                    let fingerprint = model_files_fingerprint();
+                    if fingerprint == last_seen {
+                        continue;
+                    }
+                    last_seen = fingerprint;
+
+                    tracing::info!("Model files changed, reloading");
+                    if let Err(e) = pool.reload().await {
+                        tracing::error!("Model reload failed: {}", e);
+                    }
+                }
+            });
+        }
+
+        pub async fn classify(&self, song_id: String, strategy: Ensemble) -> Result<SongClassificationResult, InferenceError> {
+            self.run(move |models| SongClassificationResult::new(models, song_id, strategy))
+                .await?
+                .map_err(InferenceError::NoFeatures)
+        }
+
+        /// Runs a blocking job against the models once a worker is free.
+        /// The permits move into the job, so a dropped request still counts until its job is done.
+        pub async fn run<T, F>(&self, job: F) -> Result<T, InferenceError>
+        where
+            T: Send + 'static,
+            F: FnOnce(&ModelRegistry) -> T + Send + 'static,
+        {
+            let admitted = self
+                .admitted
+                .clone()
+                .try_acquire_owned()
+                .map_err(|_| InferenceError::Busy)?;
+
+            let worker = self
+                .workers
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("Worker semaphore is never closed");
+
+            let models = self.models();
+
+            tokio::task::spawn_blocking(move || {
+                let _permits = (admitted, worker);
+                job(&models)
+            })
+            .await
+            .map_err(|e| InferenceError::Failed(e.to_string()))
+        }
+    }
+
+    /// Modification time and size of the manifest and of every model file it points to.
+    fn model_files_fingerprint() -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+        let manifest_path = ModelManifest::path_from_env();
+
+        let mut paths = vec![manifest_path.clone()];
+        if let Ok(manifest) = ModelManifest::load(&manifest_path) {
+            let mut model_paths: Vec<PathBuf> = manifest.models.values().map(|entry| entry.path.clone()).collect();
+            model_paths.sort();
+            paths.extend(model_paths);
+        }
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let metadata = std::fs::metadata(&path).ok();
+                let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+                let len = metadata.map(|m| m.len()).unwrap_or(0);
+                (path, modified, len)
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{collections::HashMap, sync::mpsc};
+
+        use super::*;
+        use crate::ml::manifest::ModelManifest;
+
+        /// The jobs below never touch the models, so none are loaded.
+        fn models() -> Arc<ModelRegistry> {
+            let manifest = ModelManifest::parse(include_str!("../util/models.json")).unwrap();
+            Arc::new(ModelRegistry::new(manifest, HashMap::new()))
+        }
+
+        #[tokio::test]
+        async fn rejects_requests_beyond_the_queue() {
+            let pool = InferencePool::new(models(), 1, 1);
+            let (release, wait) = mpsc::channel::<()>();
+
+            let running = tokio::spawn({
+                let pool = pool.clone();
+                async move { pool.run(move |_| wait.recv().unwrap()).await }
+            });
+            let queued = tokio::spawn({
+                let pool = pool.clone();
+                async move { pool.run(|_| 2).await }
+            });
+            tokio::task::yield_now().await;
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+            assert!(matches!(pool.run(|_| 3).await, Err(InferenceError::Busy)));
+
+            release.send(()).unwrap();
+            assert!(running.await.unwrap().is_ok());
+            assert_eq!(queued.await.unwrap().unwrap(), 2);
+            assert_eq!(pool.run(|_| 4).await.unwrap(), 4);
+        }
+
+        #[tokio::test]
+        async fn running_jobs_keep_the_registry_they_started_with() {
+            let pool = InferencePool::new(models(), 1, 1);
+            let (release, wait) = mpsc::channel::<()>();
+
+            let running = tokio::spawn({
+                let pool = pool.clone();
+                async move {
+                    pool.run(move |models| {
+                        wait.recv().unwrap();
+                        models.manifest().version.clone()
+                    })
+                    .await
+                }
+            });
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+            let mut manifest = ModelManifest::parse(include_str!("../util/models.json")).unwrap();
+            manifest.version = "2".to_string();
+            pool.swap(Arc::new(ModelRegistry::new(manifest, HashMap::new())));
+            release.send(()).unwrap();
+
+            assert_eq!(running.await.unwrap().unwrap(), "1");
+            assert_eq!(pool.run(|models| models.manifest().version.clone()).await.unwrap(), "2");
+        }
+
+        #[tokio::test]
+        async fn dropped_requests_keep_their_worker_until_the_job_ends() {
+            let pool = InferencePool::new(models(), 1, 0);
+            let (started, has_started) = mpsc::channel::<()>();
+            let (release, wait) = mpsc::channel::<()>();
+
+            let dropped = tokio::time::timeout(
+                std::time::Duration::from_millis(50),
+                pool.run(move |_| {
+                    started.send(()).unwrap();
+                    wait.recv().unwrap()
+                }),
+            )
+            .await;
+            assert!(dropped.is_err());
+            has_started.recv().unwrap();
+
+            assert!(matches!(pool.run(|_| 2).await, Err(InferenceError::Busy)));
+
+            release.send(()).unwrap();
+            let mut admitted = pool.run(|_| 3).await;
+            for _ in 0..100 {
+                if admitted.is_ok() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                admitted = pool.run(|_| 3).await;
+            }
+            assert_eq!(admitted.unwrap(), 3);
+        }
+
+        #[tokio::test]
+        async fn reports_panicking_jobs() {
+            let pool = InferencePool::new(models(), 1, 0);
+
+            let result: Result<(), _> = pool.run(|_| panic!("missing feature file")).await;
+
+            assert!(matches!(result, Err(InferenceError::Failed(_))));
         }
     }
 }
@@ -0,0 +1,651 @@
+/// Offline commands run as `back <command> [args]` instead of starting the server.
+pub fn run_command(command: &str, args: &[String]) -> Result<(), String> {
+    match command {
+        "calibrate" => calibration::run(args),
+        "eval" => evaluation::run(args),
+        "extract" => extraction::run(args),
+        _ => Err(format!(
+            "Unknown command {}. Available commands: calibrate, eval, extract",
+            command
+        )),
+    }
+}
+
+pub mod dataset {
+
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use crate::ml::ml::{calibrate, find_signal_path_in, load_signal_from, softmax, Feature, ModelRegistry};
+
+    /// A track under `<root>/<label>/<track>/`, laid out like `SERVER_DATA/features/<track>/`.
+    #[derive(Debug, Clone)]
+    pub struct LabelledTrack {
+        /// Index into the manifest's `class_labels`.
+        pub label: usize,
+        pub features_path: PathBuf,
+    }
+
+    pub fn load_labelled_tracks(root: &Path, class_labels: &[String]) -> Result<Vec<LabelledTrack>, String> {
+        let mut tracks = Vec::new();
+
+        let label_dirs = fs::read_dir(root).map_err(|e| format!("{}: {}", root.display(), e))?;
+
+        for label_dir in label_dirs.flatten() {
+            let dir_name = label_dir.file_name().to_string_lossy().to_string();
+
+            let Some(label) = class_labels.iter().position(|l| l == &dir_name) else {
+                tracing::warn!("Skipping {}, not a class label of the manifest", dir_name);
+                continue;
+            };
+
+            let track_dirs = fs::read_dir(label_dir.path()).map_err(|e| format!("{}: {}", dir_name, e))?;
+
+            for track_dir in track_dirs.flatten().filter(|d| d.path().is_dir()) {
+                tracks.push(LabelledTrack {
+                    label,
+                    features_path: track_dir.path(),
+                });
+            }
+        }
+
+        tracks.sort_by(|a, b| a.features_path.cmp(&b.features_path));
+
+        Ok(tracks)
+    }
+
+    /// Per-frame logits of one feature model for every track, uncalibrated and in the model's
+    /// output order, together with each frame's label in the same order.
+    pub fn collect_logits(
+        models: &ModelRegistry,
+        feature: &Feature,
+        tracks: &[LabelledTrack],
+    ) -> (Vec<Vec<f32>>, Vec<usize>) {
+        let entry = models.manifest().entry(feature);
+        let label_order = entry.label_order(&models.manifest().class_labels);
+
+        let mut logits = Vec::new();
+        let mut targets = Vec::new();
+
+        for track in tracks {
+            let path = find_signal_path_in(&track.features_path, feature);
+
+            let frames = match load_signal_from(&path, feature, &entry.input_shape) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    tracing::warn!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            let rows: Vec<Vec<f32>> = match models.forward(feature, &frames) {
+                Ok(output) => output.outer_iter().map(|row| row.to_vec()).collect(),
+                Err(e) => {
+                    tracing::warn!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            targets.extend(std::iter::repeat(label_order[track.label]).take(rows.len()));
+            logits.extend(rows);
+        }
+
+        (logits, targets)
+    }
+
+    /// Per-frame calibrated probabilities of one feature model, in the manifest's label order.
+    pub fn collect_probabilities(
+        models: &ModelRegistry,
+        feature: &Feature,
+        track: &LabelledTrack,
+    ) -> Result<Vec<Vec<f32>>, String> {
+        let entry = models.manifest().entry(feature);
+        let label_order = entry.label_order(&models.manifest().class_labels);
+
+        let path = find_signal_path_in(&track.features_path, feature);
+        let frames = load_signal_from(&path, feature, &entry.input_shape)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let logits = models.forward(feature, &frames).map_err(|e| e.to_string())?;
+        let probabilities = softmax(calibrate(logits, &entry.calibration));
+
+        Ok(probabilities
+            .outer_iter()
+            .map(|row| label_order.iter().map(|idx| row[*idx]).collect())
+            .collect())
+    }
+}
+
+pub mod calibration {
+
+    use std::path::Path;
+
+    use crate::ml::{
+        manifest::{Calibration, ModelManifest},
+        ml::ModelRegistry,
+    };
+    use crate::offline::dataset::{collect_logits, load_labelled_tracks};
+
+    const ITERATIONS: usize = 500;
+
+    /// `back calibrate <labelled_dir> [--bias] [--write]`
+    ///
+    /// Fits a temperature (and with `--bias` a per-class bias) for every feature model by
+    /// minimizing the frame-level negative log-likelihood on a labelled set. With `--write`
+    /// the fitted values are stored in the manifest at `MODEL_MANIFEST`.
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let root = args
+            .iter()
+            .find(|a| !a.starts_with("--"))
+            .ok_or("Usage: back calibrate <labelled_dir> [--bias] [--write]".to_string())?;
+        let fit_bias = args.iter().any(|a| a == "--bias");
+        let write = args.iter().any(|a| a == "--write");
+
+        let manifest_path = ModelManifest::path_from_env();
+        let manifest = ModelManifest::load(&manifest_path).map_err(|e| e.to_string())?;
+        let models = ModelRegistry::load(manifest.clone()).map_err(|e| e.to_string())?;
+
+        let tracks = load_labelled_tracks(Path::new(root), &manifest.class_labels)?;
+        println!("{} labelled tracks found in {}", tracks.len(), root);
+
+        let mut calibrated = manifest.clone();
+
+        for (feature, reason) in models.unavailable() {
+            println!("{:?}: model unavailable, calibration left unchanged: {}", feature, reason);
+        }
+
+        for feature in models.available_features() {
+            let (logits, targets) = collect_logits(&models, &feature, &tracks);
+
+            if logits.is_empty() {
+                println!("{:?}: no frames, calibration left unchanged", feature);
+                continue;
+            }
+
+            let before = nll(&logits, &targets, &Calibration::default());
+            let calibration = fit(&logits, &targets, fit_bias);
+            let after = nll(&logits, &targets, &calibration);
+
+            println!(
+                "{:?}: {} frames, temperature {:.4}, bias {:?}, nll {:.4} -> {:.4}",
+                feature,
+                logits.len(),
+                calibration.temperature,
+                calibration.bias,
+                before,
+                after
+            );
+
+            calibrated
+                .models
+                .get_mut(&feature)
+                .expect("Manifest is validated to contain every feature")
+                .calibration = calibration;
+        }
+
+        if write {
+            calibrated.save(&manifest_path).map_err(|e| e.to_string())?;
+            println!("Calibration written to {}", manifest_path.display());
+        }
+
+        Ok(())
+    }
+
+    /// Mean negative log-likelihood of `softmax(logits / temperature + bias)`.
+    pub fn nll(logits: &[Vec<f32>], targets: &[usize], calibration: &Calibration) -> f32 {
+        let total: f32 = logits
+            .iter()
+            .zip(targets)
+            .map(|(row, target)| {
+                let z = calibration.apply(row);
+                log_sum_exp(&z) - z[*target]
+            })
+            .sum();
+
+        total / logits.len() as f32
+    }
+
+    /// Gradient descent on `ln(temperature)` and the bias, halving the step whenever the loss grows.
+    pub fn fit(logits: &[Vec<f32>], targets: &[usize], fit_bias: bool) -> Calibration {
+        let class_count = logits.first().map(|row| row.len()).unwrap_or(0);
+
+        let mut calibration = Calibration {
+            temperature: 1.0,
+            bias: fit_bias.then(|| vec![0.0; class_count]),
+        };
+        let mut loss = nll(logits, targets, &calibration);
+        let mut step = 0.1;
+
+        for _ in 0..ITERATIONS {
+            let (grad_log_t, grad_bias) = gradients(logits, targets, &calibration);
+
+            let candidate = Calibration {
+                temperature: (calibration.temperature.ln() - step * grad_log_t).exp(),
+                bias: calibration.bias.as_ref().map(|bias| {
+                    bias.iter()
+                        .zip(&grad_bias)
+                        .map(|(b, g)| b - step * g)
+                        .collect()
+                }),
+            };
+            let candidate_loss = nll(logits, targets, &candidate);
+
+            if candidate_loss <= loss {
+                calibration = candidate;
+                loss = candidate_loss;
+            } else {
+                step *= 0.5;
+            }
+        }
+
+        calibration
+    }
+
+    fn gradients(logits: &[Vec<f32>], targets: &[usize], calibration: &Calibration) -> (f32, Vec<f32>) {
+        let class_count = logits.first().map(|row| row.len()).unwrap_or(0);
+        let mut grad_log_t = 0.0;
+        let mut grad_bias = vec![0.0; class_count];
+
+        for (row, target) in logits.iter().zip(targets) {
+            let z = calibration.apply(row);
+            let lse = log_sum_exp(&z);
+
+            for (idx, l) in row.iter().enumerate() {
+                let d_z = (z[idx] - lse).exp() - if idx == *target { 1.0 } else { 0.0 };
+                // z = l / T + b, so dz/d(ln T) = -l / T
+                grad_log_t += d_z * -l / calibration.temperature;
+                grad_bias[idx] += d_z;
+            }
+        }
+
+        let count = logits.len() as f32;
+        (
+            grad_log_t / count,
+            grad_bias.iter().map(|g| g / count).collect(),
+        )
+    }
+
+    fn log_sum_exp(values: &[f32]) -> f32 {
+        let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        max + values.iter().map(|v| (v - max).exp()).sum::<f32>().ln()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn overconfident_model_gets_temperature_above_one() {
+            // the model is always 98% sure of class 0, but right only 80% of the time
+            let logits = vec![vec![4.0, 0.0]; 10];
+            let targets = vec![0, 0, 0, 0, 1, 0, 0, 0, 0, 1];
+
+            let calibration = fit(&logits, &targets, false);
+
+            // softmax([4 / T, 0]) = [0.8, 0.2] for T = 4 / ln(4)
+            assert!((calibration.temperature - 4.0 / 4.0_f32.ln()).abs() < 0.05);
+            assert!(nll(&logits, &targets, &calibration) < nll(&logits, &targets, &Calibration::default()));
+        }
+
+        #[test]
+        fn underconfident_model_gets_temperature_below_one() {
+            let logits = vec![vec![0.5, 0.0], vec![0.0, 0.5]];
+            let targets = vec![0, 1];
+
+            let calibration = fit(&logits, &targets, false);
+
+            assert!(calibration.temperature < 1.0);
+        }
+
+        #[test]
+        fn bias_learns_class_prior() {
+            let logits = vec![vec![0.0, 0.0]; 4];
+            let targets = vec![0, 0, 0, 1];
+
+            let calibration = fit(&logits, &targets, true);
+            let bias = calibration.bias.unwrap();
+
+            assert!((bias[0] - bias[1] - 3.0_f32.ln()).abs() < 0.05);
+        }
+    }
+}
+
+pub mod evaluation {
+
+    use std::{collections::HashMap, path::Path};
+
+    use crate::ml::{
+        ensemble::{argmax, Ensemble},
+        manifest::ModelManifest,
+        ml::{Feature, FeatureClassificationResult, ModelRegistry, SongClassificationResult},
+    };
+    use crate::offline::dataset::{collect_probabilities, load_labelled_tracks, LabelledTrack};
+
+    /// Rows are the actual class, columns the predicted one.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ConfusionMatrix {
+        pub counts: Vec<Vec<usize>>,
+    }
+
+    impl ConfusionMatrix {
+        pub fn new(class_count: usize) -> Self {
+            Self {
+                counts: vec![vec![0; class_count]; class_count],
+            }
+        }
+
+        pub fn add(&mut self, actual: usize, predicted: usize) {
+            self.counts[actual][predicted] += 1;
+        }
+
+        pub fn total(&self) -> usize {
+            self.counts.iter().flatten().sum()
+        }
+
+        pub fn accuracy(&self) -> f32 {
+            let correct: usize = (0..self.counts.len()).map(|idx| self.counts[idx][idx]).sum();
+
+            match self.total() {
+                0 => 0.0,
+                total => correct as f32 / total as f32,
+            }
+        }
+
+        /// `None` when the class was never predicted.
+        pub fn precision(&self, class: usize) -> Option<f32> {
+            let predicted: usize = self.counts.iter().map(|row| row[class]).sum();
+            (predicted > 0).then(|| self.counts[class][class] as f32 / predicted as f32)
+        }
+
+        /// `None` when the class never occurs in the set.
+        pub fn recall(&self, class: usize) -> Option<f32> {
+            let actual: usize = self.counts[class].iter().sum();
+            (actual > 0).then(|| self.counts[class][class] as f32 / actual as f32)
+        }
+
+        pub fn render(&self, class_labels: &[String]) -> String {
+            let width = class_labels.iter().map(|l| l.len()).max().unwrap_or(0).max(9);
+            let mut out = format!("{:>width$}", "actual", width = width);
+
+            for label in class_labels {
+                out.push_str(&format!(" {:>width$}", label, width = width));
+            }
+            out.push_str(&format!(" {:>9} {:>9}\n", "precision", "recall"));
+
+            for (idx, row) in self.counts.iter().enumerate() {
+                out.push_str(&format!("{:>width$}", class_labels[idx], width = width));
+
+                for count in row {
+                    out.push_str(&format!(" {:>width$}", count, width = width));
+                }
+
+                out.push_str(&format!(
+                    " {:>9} {:>9}\n",
+                    percentage(self.precision(idx)),
+                    percentage(self.recall(idx))
+                ));
+            }
+
+            out
+        }
+    }
+
+    fn percentage(value: Option<f32>) -> String {
+        value.map(|v| format!("{:.2}%", v * 100.0)).unwrap_or("-".to_string())
+    }
+
+    /// Proposed weight of each feature model is its track-level accuracy on the labelled set,
+    /// the same way the bundled weights follow the validation accuracies.
+    pub fn propose_weights(accuracies: &HashMap<Feature, f32>) -> HashMap<Feature, f32> {
+        accuracies
+            .iter()
+            .map(|(feature, accuracy)| (feature.clone(), ((accuracy * 100.0).round() / 100.0).max(0.01)))
+            .collect()
+    }
+
+    /// Per-feature results of every track whose features could all be loaded,
+    /// in `features` order.
+    fn classify_tracks(
+        models: &ModelRegistry,
+        features: &[Feature],
+        tracks: &[LabelledTrack],
+    ) -> Vec<(usize, Vec<FeatureClassificationResult>)> {
+        let mut results = Vec::new();
+
+        'tracks: for track in tracks {
+            let mut classifications = Vec::new();
+
+            for feature in features.iter().cloned() {
+                match collect_probabilities(models, &feature, track) {
+                    Ok(rows) => classifications.push(FeatureClassificationResult::from_frames(
+                        feature.clone(),
+                        models.manifest().entry(&feature).weight,
+                        rows.into_iter().enumerate().map(|(idx, row)| (idx as i64, row)).collect(),
+                    )),
+                    Err(e) => {
+                        tracing::warn!("Skipping {}: {}", track.features_path.display(), e);
+                        continue 'tracks;
+                    }
+                }
+            }
+
+            results.push((track.label, classifications));
+        }
+
+        results
+    }
+
+    fn ensemble_matrix(
+        class_labels: &[String],
+        results: &[(usize, Vec<FeatureClassificationResult>)],
+        strategy: Ensemble,
+    ) -> ConfusionMatrix {
+        let mut matrix = ConfusionMatrix::new(class_labels.len());
+
+        for (label, classifications) in results {
+            let song = SongClassificationResult::from_features(
+                String::new(),
+                class_labels.to_vec(),
+                classifications.clone(),
+                Vec::new(),
+                strategy,
+            );
+            matrix.add(*label, song.major_class.index);
+        }
+
+        matrix
+    }
+
+    /// `back eval <labelled_dir> [--strategy <strategy>] [--write]`
+    ///
+    /// Reports track-level accuracy, per-class precision/recall and confusion matrices for every
+    /// feature model and for the ensemble, then proposes weights from the feature accuracies.
+    /// With `--write` the proposed weights and accuracies are stored in the manifest at `MODEL_MANIFEST`.
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: back eval <labelled_dir> [--strategy <strategy>] [--write]";
+
+        let mut root = None;
+        let mut strategy = Ensemble::from_env()?;
+        let mut write = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--write" => write = true,
+                "--strategy" => {
+                    strategy = args.next().ok_or(usage.to_string())?.parse()?;
+                }
+                _ if root.is_none() => root = Some(arg.clone()),
+                _ => return Err(usage.to_string()),
+            }
+        }
+        let root = root.ok_or(usage.to_string())?;
+
+        let manifest_path = ModelManifest::path_from_env();
+        let manifest = ModelManifest::load(&manifest_path).map_err(|e| e.to_string())?;
+        let models = ModelRegistry::load(manifest.clone()).map_err(|e| e.to_string())?;
+        let class_labels = &manifest.class_labels;
+
+        for (feature, reason) in models.unavailable() {
+            println!("{:?}: model unavailable, left out of the evaluation: {}", feature, reason);
+        }
+        let features = models.available_features();
+
+        let tracks = load_labelled_tracks(Path::new(&root), class_labels)?;
+        let results = classify_tracks(&models, &features, &tracks);
+        println!("{} of {} labelled tracks evaluated", results.len(), tracks.len());
+
+        if results.is_empty() {
+            return Err("No track could be evaluated".to_string());
+        }
+
+        let mut accuracies = HashMap::new();
+
+        for (idx, feature) in features.iter().enumerate() {
+            let mut matrix = ConfusionMatrix::new(class_labels.len());
+
+            for (label, classifications) in &results {
+                matrix.add(*label, argmax(&classifications[idx].avg_classification));
+            }
+
+            println!("\n{:?}: accuracy {:.2}%", feature, matrix.accuracy() * 100.0);
+            println!("{}", matrix.render(class_labels));
+
+            accuracies.insert(feature.clone(), matrix.accuracy());
+        }
+
+        let matrix = ensemble_matrix(class_labels, &results, strategy);
+        println!("\nEnsemble ({}): accuracy {:.2}%", strategy, matrix.accuracy() * 100.0);
+        println!("{}", matrix.render(class_labels));
+
+        let weights = propose_weights(&accuracies);
+
+        let reweighted: Vec<(usize, Vec<FeatureClassificationResult>)> = results
+            .iter()
+            .map(|(label, classifications)| {
+                let classifications = classifications
+                    .iter()
+                    .map(|c| {
+                        FeatureClassificationResult::from_frames(
+                            c.feature.clone(),
+                            weights[&c.feature],
+                            c.per_frame_classifications.clone(),
+                        )
+                    })
+                    .collect();
+                (*label, classifications)
+            })
+            .collect();
+
+        println!("\nProposed weights:");
+        for feature in &features {
+            println!(
+                "{:>20} {:.2} (currently {:.2})",
+                feature.key(),
+                weights[feature],
+                manifest.entry(feature).weight
+            );
+        }
+
+        let matrix = ensemble_matrix(class_labels, &reweighted, strategy);
+        println!(
+            "Ensemble ({}) with proposed weights: accuracy {:.2}%",
+            strategy,
+            matrix.accuracy() * 100.0
+        );
+
+        if write {
+            let mut updated = manifest.clone();
+
+            for (feature, entry) in updated.models.iter_mut().filter(|(f, _)| weights.contains_key(*f)) {
+                entry.weight = weights[feature];
+                entry.validation_accuracy = Some(accuracies[feature]);
+            }
+
+            updated.save(&manifest_path).map_err(|e| e.to_string())?;
+            println!("Weights written to {}", manifest_path.display());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn matrix() -> ConfusionMatrix {
+            let mut matrix = ConfusionMatrix::new(3);
+            // class 0: 3 right, 1 taken for class 1
+            (0..3).for_each(|_| matrix.add(0, 0));
+            matrix.add(0, 1);
+            // class 1: 2 right
+            (0..2).for_each(|_| matrix.add(1, 1));
+            matrix
+        }
+
+        #[test]
+        fn confusion_matrix_accuracy() {
+            assert_eq!(matrix().total(), 6);
+            assert!((matrix().accuracy() - 5.0 / 6.0).abs() < 1e-6);
+            assert_eq!(ConfusionMatrix::new(3).accuracy(), 0.0);
+        }
+
+        #[test]
+        fn confusion_matrix_precision_and_recall() {
+            let matrix = matrix();
+
+            assert_eq!(matrix.precision(0), Some(1.0));
+            assert_eq!(matrix.recall(0), Some(0.75));
+            assert!((matrix.precision(1).unwrap() - 2.0 / 3.0).abs() < 1e-6);
+            assert_eq!(matrix.recall(1), Some(1.0));
+            assert_eq!(matrix.precision(2), None);
+            assert_eq!(matrix.recall(2), None);
+        }
+
+        #[test]
+        fn proposed_weights_follow_accuracy() {
+            let accuracies = HashMap::from([(Feature::Mfcc, 0.786), (Feature::Tonnetz, 0.0)]);
+
+            let weights = propose_weights(&accuracies);
+
+            assert_eq!(weights[&Feature::Mfcc], 0.79);
+            // manifest weights must stay positive
+            assert_eq!(weights[&Feature::Tonnetz], 0.01);
+        }
+    }
+}
+
+pub mod extraction {
+
+    use std::{fs::File, path::Path};
+
+    use ndarray::Array1;
+    use ndarray_npy::ReadNpyExt;
+
+    use crate::features::{extract_to, FeatureExtractor, NormalizationStats};
+
+    /// `back extract <signal.npy> <features_dir>`
+    ///
+    /// Computes every feature of a mono 22050 Hz float32 signal the way backend-etl does and
+    /// writes them to `<features_dir>/<feature>/<feature>.npy`. Normalization stats are read
+    /// from `METADATA`.
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let [signal_path, features_dir] = args else {
+            return Err("Usage: back extract <signal.npy> <features_dir>".to_string());
+        };
+
+        let file = File::open(signal_path).map_err(|e| format!("{}: {}", signal_path, e))?;
+        let signal = Array1::<f32>::read_npy(file).map_err(|e| format!("{}: {}", signal_path, e))?;
+        println!("{}: {} samples", signal_path, signal.len());
+
+        let stats = NormalizationStats::from_env();
+        let mut extractor = FeatureExtractor::new();
+
+        extract_to(&signal.to_vec(), Path::new(features_dir), &stats, &mut extractor).map_err(|e| e.to_string())?;
+        println!("Features written to {}", features_dir);
+
+        Ok(())
+    }
+}
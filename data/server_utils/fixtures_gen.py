@@ -0,0 +1,65 @@
+"""
+Writes the fixtures the backend's native feature extraction is checked against.
+
+Run from data/:  python -m server_utils.fixtures_gen ../back/util/fixtures/features [metadata]
+
+The signal is synthetic so the fixtures can be regenerated anywhere. Only the first
+FIXTURE_FRAMES frames are transformed to keep the files small.
+"""
+import os
+import sys
+
+import numpy as np
+
+from server_utils import artifacts_gen
+
+
+FIXTURE_FRAMES = 4
+SAMPLE_RATE = 22050
+
+
+def synthetic_signal(seconds=31):
+    t = np.arange(seconds * SAMPLE_RATE) / SAMPLE_RATE
+    rng = np.random.default_rng(0)
+
+    y = 0.5 * np.sin(2 * np.pi * 220 * t)
+    y += 0.3 * np.sin(2 * np.pi * 329.63 * t)
+    # vibrato around A4 so tuning estimation isn't trivially zero
+    y += 0.2 * np.sin(2 * np.pi * 440 * t + 3 * np.sin(2 * np.pi * 5 * t))
+    y += 0.02 * rng.standard_normal(len(t))
+
+    return y.astype(np.float32)
+
+
+def save(out_dir, name, values):
+    feature_dir = os.path.join(out_dir, name)
+    os.makedirs(feature_dir, exist_ok=True)
+    np.save(os.path.join(feature_dir, f"{name}.npy"), values)
+
+
+def main(out_dir, metadata):
+    os.makedirs(out_dir, exist_ok=True)
+
+    y = synthetic_signal()
+    np.save(os.path.join(out_dir, "signal.npy"), y)
+
+    y_30 = artifacts_gen.extract_y_middle(y, 30)
+    frames = artifacts_gen.split_to_frames(y_30, frame_length=22050, hop_length=2205)[:FIXTURE_FRAMES]
+    np.save(os.path.join(out_dir, "frames.npy"), frames.astype(np.float32))
+
+    # directory names as in SERVER_DATA/features/<upload>/
+    save(out_dir, "ft", artifacts_gen.transform_to_ft(frames, metadata, True))
+    save(out_dir, "spectr", artifacts_gen.transform_to_spectr(frames, metadata, True))
+    save(out_dir, "mel_spectr", artifacts_gen.transform_to_mel_spectr(frames, metadata, True))
+    save(out_dir, "power_spectr", artifacts_gen.transform_to_power_spectr(frames, metadata, True))
+    save(out_dir, "mfcc", artifacts_gen.transform_to_mfcc(frames, metadata, True))
+    for transformation in ["stft", "cens", "cqt"]:
+        save(out_dir, f"chroma_{transformation}", artifacts_gen.transform_to_chroma(frames, metadata, transformation, True))
+    save(out_dir, "tonnetz", artifacts_gen.transform_to_tonnetz(frames, metadata, True))
+
+
+if __name__ == "__main__":
+    if len(sys.argv) < 2:
+        sys.exit("Usage: python -m server_utils.fixtures_gen <out_dir> [metadata]")
+
+    main(sys.argv[1], sys.argv[2] if len(sys.argv) > 2 else "artifacts")